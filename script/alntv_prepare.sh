@@ -1,7 +1,7 @@
 #!/bin/bash
 set -uex
 ## Synopsis: <REFERENCE:fasta> <Annotation:GFF3> <PREFIX:Dirname> <CONTIGS:[fasta]>
-## Require: minimap2, liftoff, last,
+## Require: minimap2, rust, last,
 REFERENCE=$1
 ANNOTATION=$2
 PREFIX=$3
@@ -21,17 +21,21 @@ done
 awk '($0 ~ />/){print(">ref");next}{print $0}' "$REFERENCE" > "$PREFIX"/reference.fa
 
 ### 1. liftover alignments
-function liftover_by_liftoff () {
+function liftover_by_maf () {
     GFFFILE=$1
     TARGET_CTG=$2
     REF_CTG=$3
     OUT_GFF=${TARGET_CTG%.fa}.gff3
     OUT_TSV=${TARGET_CTG%.fa}.tsv
+    OUT_MAF=${TARGET_CTG%.fa}.lift.maf
     TEMP="$RANDOM"
-    liftoff -o "$OUT_GFF" -dir "$TEMP" -g "$GFFFILE" "$TARGET_CTG" "$REF_CTG"
+    lastdb "$TEMP"_db "$REF_CTG"
+    last-train "$TEMP"_db "$TARGET_CTG" > "$TEMP".par
+    lastal -p "$TEMP".par "$TEMP"_db "$TARGET_CTG" | last-split | last-split -r > "$OUT_MAF"
+    cargo run --release --bin lift_annotation -- --gff "$GFFFILE" --maf "$OUT_MAF" --output "$OUT_GFF" --report "${TARGET_CTG%.fa}.lift.tsv"
     grep -v "^#" "$OUT_GFF" |\
         awk 'BEGIN{OFS="\t"}($3 ~/gene/){if ($7 == "+"){ dir = "1"} else { dir = "-1"};print($1,$4,$5,dir,"gene")}' > "$OUT_TSV"
-    rm -r "$TEMP"
+    rm "$TEMP"_db* "$TEMP".par
 }
 
 awk '($0 ~ /^#/){print $0;next} ($0 ~ /^NC_037304.1/){print $0}' "$ANNOTATION" | sed -e 's/NC_037304.1/ref/g'> "$PREFIX"/reference.gff3
@@ -41,7 +45,7 @@ for contig in $CONTIGS
 do
     strain=$( basename "$contig" )    
     TARGET="$PREFIX"/"$strain"
-    liftover_by_liftoff "$PREFIX"/reference.gff3 "$TARGET" "$PREFIX"/reference.fa
+    liftover_by_maf "$PREFIX"/reference.gff3 "$TARGET" "$PREFIX"/reference.fa
 done
 
 ### 2. All vs All alignments
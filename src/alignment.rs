@@ -0,0 +1,290 @@
+//! Walking CIGAR strings of pairwise alignments, such as PAF records with `cg` tags.
+use bio_utils::paf::PAF;
+use bio_utils::sam::Op;
+
+/// The kind of an aligned column. `M` operations are regarded as matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Event {
+    Match,
+    Mismatch,
+    Ins,
+    Del,
+}
+
+/// A run of aligned columns with the same event. Positions are 0-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    pub event: Event,
+    pub len: usize,
+    /// Reference position of the first column. Inserted bases precede this position.
+    pub rpos: usize,
+    /// Offset of the first column in the aligned region of the query, oriented to the reference.
+    pub offset: usize,
+    /// Interval `[qstart, qend)` of the query bases in the column, in the forward strand of the query.
+    /// Empty, and located at the deleted position, for deletions.
+    pub qstart: usize,
+    pub qend: usize,
+}
+
+impl Column {
+    /// The reference position just after the column.
+    pub fn rend(&self) -> usize {
+        match self.event {
+            Event::Ins => self.rpos,
+            _ => self.rpos + self.len,
+        }
+    }
+    /// The offset in the aligned region of the query just after the column.
+    pub fn offset_end(&self) -> usize {
+        match self.event {
+            Event::Del => self.offset,
+            _ => self.offset + self.len,
+        }
+    }
+    /// Split the column into columns of length one, in the reference order.
+    pub fn split(self, is_forward: bool) -> impl Iterator<Item = Column> {
+        (0..self.len).map(move |i| {
+            let (rpos, offset) = match self.event {
+                Event::Ins => (self.rpos, self.offset + i),
+                Event::Del => (self.rpos + i, self.offset),
+                _ => (self.rpos + i, self.offset + i),
+            };
+            let (qstart, qend) = match (self.event, is_forward) {
+                (Event::Del, _) => (self.qstart, self.qend),
+                (_, true) => (self.qstart + i, self.qstart + i + 1),
+                (_, false) => (self.qend - i - 1, self.qend - i),
+            };
+            Column {
+                event: self.event,
+                len: 1,
+                rpos,
+                offset,
+                qstart,
+                qend,
+            }
+        })
+    }
+}
+
+/// Walk the CIGAR of an alignment starting at `rstart` in the reference and spanning `[qstart, qend)`
+/// of the query (forward strand). Clipping and padding operations are skipped.
+pub fn walk(
+    cigar: &[Op],
+    rstart: usize,
+    (qstart, qend): (usize, usize),
+    is_forward: bool,
+) -> Vec<Column> {
+    let mut columns = vec![];
+    let (mut rpos, mut offset) = (rstart, 0);
+    for op in cigar.iter() {
+        let (event, len) = match *op {
+            Op::Align(l) | Op::Match(l) => (Event::Match, l),
+            Op::Mismatch(l) => (Event::Mismatch, l),
+            Op::Insertion(l) => (Event::Ins, l),
+            Op::Deletion(l) | Op::Skipped(l) => (Event::Del, l),
+            Op::SoftClip(_) | Op::HardClip(_) | Op::Padding(_) => continue,
+        };
+        let qlen = match event {
+            Event::Del => 0,
+            _ => len,
+        };
+        let (start, end) = match is_forward {
+            true => (qstart + offset, qstart + offset + qlen),
+            false => (qend - offset - qlen, qend - offset),
+        };
+        let column = Column {
+            event,
+            len,
+            rpos,
+            offset,
+            qstart: start,
+            qend: end,
+        };
+        rpos = column.rend();
+        offset = column.offset_end();
+        columns.push(column);
+    }
+    columns
+}
+
+/// Walk the `cg` tag of a PAF record. Return `None` if the record does not have the tag.
+pub fn walk_paf(paf: &PAF) -> Option<Vec<Column>> {
+    let cigar = bio_utils::sam::parse_cigar_string(paf.get_tag("cg")?.1);
+    let query = (paf.qstart, paf.qend);
+    Some(walk(&cigar, paf.tstart, query, paf.relstrand))
+}
+
+/// Convert CIGAR operations into kiley's operations. Clipping, skipping, and padding operations are ignored.
+pub fn to_kiley_ops(cigar: &[Op]) -> Vec<kiley::Op> {
+    cigar
+        .iter()
+        .flat_map(|op| match *op {
+            Op::Match(l) | Op::Mismatch(l) | Op::Align(l) => vec![kiley::Op::Match; l],
+            Op::Deletion(l) => vec![kiley::Op::Del; l],
+            Op::Insertion(l) => vec![kiley::Op::Ins; l],
+            _ => vec![],
+        })
+        .collect()
+}
+
+/// Convert kiley's operations into CIGAR operations. Matches and mismatches are merged into `M`.
+pub fn to_sam_ops(k_ops: &[kiley::Op]) -> Vec<Op> {
+    let mut ops: Vec<Op> = vec![];
+    for &op in k_ops.iter() {
+        match (op, ops.last_mut()) {
+            (kiley::Op::Del, Some(Op::Deletion(l))) => *l += 1,
+            (kiley::Op::Ins, Some(Op::Insertion(l))) => *l += 1,
+            (kiley::Op::Match | kiley::Op::Mismatch, Some(Op::Align(l))) => *l += 1,
+            (kiley::Op::Del, _) => ops.push(Op::Deletion(1)),
+            (kiley::Op::Ins, _) => ops.push(Op::Insertion(1)),
+            (kiley::Op::Match | kiley::Op::Mismatch, _) => ops.push(Op::Align(1)),
+        }
+    }
+    ops
+}
+
+/// Format CIGAR operations as a CIGAR string.
+pub fn to_cigar_string(cigar: &[Op]) -> String {
+    cigar
+        .iter()
+        .map(|op| match *op {
+            Op::Align(l) => format!("{l}M"),
+            Op::Insertion(l) => format!("{l}I"),
+            Op::Deletion(l) => format!("{l}D"),
+            Op::Skipped(l) => format!("{l}N"),
+            Op::SoftClip(l) => format!("{l}S"),
+            Op::HardClip(l) => format!("{l}H"),
+            Op::Padding(l) => format!("{l}P"),
+            Op::Match(l) => format!("{l}="),
+            Op::Mismatch(l) => format!("{l}X"),
+        })
+        .collect()
+}
+
+/// Re-align the read of a SAM record to `contig`, guided by the CIGAR of the record.
+/// `params` are the match score, the mismatch score, the gap open, and the gap extension penalty.
+/// Clipping operations are retained, and deletions at both ends are removed.
+/// Return the new CIGAR and the new 0-based start position of the alignment.
+/// Return the original CIGAR and start if the record does not have the sequence or runs past the end of `contig`.
+pub fn re_align(
+    aln: &bio_utils::sam::Sam,
+    contig: &[u8],
+    radius: usize,
+    params: (i32, i32, i32, i32),
+) -> (Vec<Op>, usize) {
+    let cigar = aln.cigar();
+    let seq = aln.seq().as_bytes();
+    let (start, end) = aln.get_range();
+    let is_clip = |op: &&Op| matches!(op, Op::SoftClip(_) | Op::HardClip(_));
+    let head: Vec<_> = cigar.iter().take_while(is_clip).cloned().collect();
+    let tail: Vec<_> = cigar.iter().rev().take_while(is_clip).cloned().collect();
+    if seq == b"*" || head.len() == cigar.len() || contig.len() < end || end <= start {
+        return (cigar, start);
+    }
+    let soft_clip = |ops: &[Op]| -> usize {
+        ops.iter()
+            .map(|op| match op {
+                Op::SoftClip(l) => *l,
+                _ => 0,
+            })
+            .sum()
+    };
+    let seq = &seq[soft_clip(&head)..seq.len() - soft_clip(&tail)];
+    let refr = &contig[start..end];
+    let guide = to_kiley_ops(&cigar);
+    let (_, ops) = kiley::bialignment::guided::global_guided(refr, seq, &guide, radius, params);
+    let mut ops = to_sam_ops(&ops);
+    let mut start = start;
+    while let Some(&Op::Deletion(l)) = ops.first() {
+        start += l;
+        ops.remove(0);
+    }
+    while let Some(Op::Deletion(_)) = ops.last() {
+        ops.pop();
+    }
+    let mut polished = head;
+    polished.extend(ops);
+    polished.extend(tail.into_iter().rev());
+    (polished, start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // 2=1I1X1D2=, aligned to [10,16) of the reference and [100,106) of the query.
+    fn cigar() -> Vec<Op> {
+        vec![
+            Op::Match(2),
+            Op::Insertion(1),
+            Op::Mismatch(1),
+            Op::Deletion(1),
+            Op::Match(2),
+        ]
+    }
+    fn intervals(columns: &[Column]) -> Vec<(Event, usize, usize, usize)> {
+        columns
+            .iter()
+            .map(|c| (c.event, c.rpos, c.qstart, c.qend))
+            .collect()
+    }
+    #[test]
+    fn forward_strand() {
+        let columns = walk(&cigar(), 10, (100, 106), true);
+        let answer = vec![
+            (Event::Match, 10, 100, 102),
+            (Event::Ins, 12, 102, 103),
+            (Event::Mismatch, 12, 103, 104),
+            (Event::Del, 13, 104, 104),
+            (Event::Match, 14, 104, 106),
+        ];
+        assert_eq!(intervals(&columns), answer);
+        assert_eq!(columns.last().map(|c| c.rend()), Some(16));
+        assert_eq!(columns.last().map(|c| c.offset_end()), Some(6));
+    }
+    #[test]
+    fn reverse_strand() {
+        let columns = walk(&cigar(), 10, (100, 106), false);
+        let answer = vec![
+            (Event::Match, 10, 104, 106),
+            (Event::Ins, 12, 103, 104),
+            (Event::Mismatch, 12, 102, 103),
+            (Event::Del, 13, 102, 102),
+            (Event::Match, 14, 100, 102),
+        ];
+        assert_eq!(intervals(&columns), answer);
+        let offsets: Vec<_> = columns.iter().map(|c| c.offset).collect();
+        assert_eq!(offsets, vec![0, 2, 3, 4, 4]);
+    }
+    #[test]
+    fn split_columns() {
+        let columns = walk(&cigar(), 10, (100, 106), false);
+        let head: Vec<_> = columns[0].split(false).collect();
+        assert_eq!(
+            intervals(&head),
+            vec![(Event::Match, 10, 105, 106), (Event::Match, 11, 104, 105)]
+        );
+        let columns = walk(&[Op::Deletion(2), Op::Insertion(2)], 10, (100, 102), true);
+        let split: Vec<_> = columns.iter().flat_map(|c| c.split(true)).collect();
+        let answer = vec![
+            (Event::Del, 10, 100, 100),
+            (Event::Del, 11, 100, 100),
+            (Event::Ins, 12, 100, 101),
+            (Event::Ins, 12, 101, 102),
+        ];
+        assert_eq!(intervals(&split), answer);
+    }
+    #[test]
+    fn kiley_ops() {
+        let ops = to_kiley_ops(&cigar());
+        assert_eq!(ops.len(), 7);
+        let sam_ops = vec![
+            Op::Align(2),
+            Op::Insertion(1),
+            Op::Align(1),
+            Op::Deletion(1),
+            Op::Align(2),
+        ];
+        assert_eq!(to_sam_ops(&ops), sam_ops);
+        assert_eq!(to_cigar_string(&sam_ops), "2M1I1M1D2M");
+    }
+}
@@ -0,0 +1,316 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+/// Call heteroplasmic sites from read alignments and output them in VCF format.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Alignments in the SAM file format. If -, use stdin.
+    #[arg(short, long)]
+    alignments: PathBuf,
+    /// The reference.
+    #[arg(short, long)]
+    reference: PathBuf,
+    /// Output VCF file.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Bases below this quality are ignored. Phred scores in SAM are at most 93.
+    #[arg(long, default_value_t = 13, value_parser = clap::value_parser!(u8).range(..=93))]
+    min_base_quality: u8,
+    /// Alignments below this mapping quality are ignored.
+    #[arg(long, default_value_t = 20)]
+    min_mapq: u8,
+    /// Minimum depth to call variants.
+    #[arg(long, default_value_t = 20)]
+    min_depth: usize,
+    /// Minimum allele fraction of heteroplasmic variants.
+    #[arg(long, default_value_t = 0.01)]
+    min_allele_fraction: f64,
+    /// Sequencing error rate for each alternative allele.
+    #[arg(long, default_value_t = 0.005)]
+    error_rate: f64,
+    /// Overdispersion of the error model. If positive, use beta-binomial test instead of binomial test.
+    #[arg(long, default_value_t = 0f64)]
+    overdispersion: f64,
+    /// Maximum p-value to call variants.
+    #[arg(long, default_value_t = 0.001)]
+    max_p_value: f64,
+}
+
+use std::io::*;
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+    let reference = bio_utils::fasta::parse_into_vec(&args.reference)?;
+    let mut pileups: HashMap<_, _> = reference
+        .iter()
+        .map(|r| (r.id().to_string(), Pileup::new(r.seq().len())))
+        .collect();
+    if args.alignments.as_os_str() == "-" {
+        let stdin = std::io::stdin();
+        let alignments = BufReader::new(stdin.lock()).lines().filter_map(|l| l.ok());
+        register_alignments(alignments, &mut pileups, &args);
+    } else {
+        let alignments = std::fs::File::open(&args.alignments).map(BufReader::new)?;
+        let alignments = alignments.lines().filter_map(|l| l.ok());
+        register_alignments(alignments, &mut pileups, &args);
+    }
+    let mut wtr = std::fs::File::create(&args.output).map(BufWriter::new)?;
+    writeln!(&mut wtr, "##fileformat=VCFv4.2")?;
+    writeln!(&mut wtr, "##source=mito_check call_heteroplasmy")?;
+    writeln!(&mut wtr, "##reference={}", args.reference.display())?;
+    for record in reference.iter() {
+        writeln!(
+            &mut wtr,
+            "##contig=<ID={},length={}>",
+            record.id(),
+            record.seq().len()
+        )?;
+    }
+    writeln!(
+        &mut wtr,
+        "##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth of the position\">"
+    )?;
+    writeln!(
+        &mut wtr,
+        "##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele fraction\">"
+    )?;
+    writeln!(&mut wtr, "##INFO=<ID=DP4,Number=4,Type=Integer,Description=\"Forward reference, reverse reference, forward alternative, and reverse alternative\">")?;
+    writeln!(&mut wtr, "##INFO=<ID=PV,Number=A,Type=Float,Description=\"P-value against the sequencing error model\">")?;
+    writeln!(&mut wtr, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")?;
+    for record in reference.iter() {
+        let pileup = &pileups[record.id()];
+        for call in call_variants(record.seq(), pileup, &args) {
+            let (refr, alt) = (call.refr, call.alt);
+            let (refr, alt) = (
+                String::from_utf8_lossy(&refr),
+                String::from_utf8_lossy(&alt),
+            );
+            let qual = (-10f64 * call.p_value.max(f64::MIN_POSITIVE).log10()).min(999f64);
+            let [fr, rr, fa, ra] = call.dp4;
+            writeln!(
+                &mut wtr,
+                "{}\t{}\t.\t{refr}\t{alt}\t{qual:.1}\tPASS\tDP={};AF={:.4};DP4={fr},{rr},{fa},{ra};PV={:.3e}",
+                record.id(),
+                call.position + 1,
+                call.depth,
+                call.fraction,
+                call.p_value
+            )?;
+        }
+    }
+    Ok(())
+}
+
+use std::collections::HashMap;
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Indel {
+    // Deletion of the bases from the position.
+    Del(usize),
+    // Insertion before the position.
+    Ins(Vec<u8>),
+}
+
+// Counts are [forward, reverse].
+#[derive(Debug, Clone)]
+struct Pileup {
+    // A, C, G, T for each position.
+    bases: Vec<[[usize; 2]; 4]>,
+    // Number of reads deleting each position.
+    deleted: Vec<[usize; 2]>,
+    indels: Vec<HashMap<Indel, [usize; 2]>>,
+}
+
+impl Pileup {
+    fn new(len: usize) -> Self {
+        Self {
+            bases: vec![[[0; 2]; 4]; len],
+            deleted: vec![[0; 2]; len],
+            indels: vec![HashMap::new(); len],
+        }
+    }
+    fn depth(&self, pos: usize) -> usize {
+        let bases: usize = self.bases[pos].iter().flatten().sum();
+        bases + self.deleted[pos].iter().sum::<usize>()
+    }
+}
+
+const BASE2IDX: [Option<usize>; 256] = base2idx();
+const fn base2idx() -> [Option<usize>; 256] {
+    let mut slots = [None; 256];
+    slots[b'A' as usize] = Some(0);
+    slots[b'a' as usize] = Some(0);
+    slots[b'C' as usize] = Some(1);
+    slots[b'c' as usize] = Some(1);
+    slots[b'G' as usize] = Some(2);
+    slots[b'g' as usize] = Some(2);
+    slots[b'T' as usize] = Some(3);
+    slots[b't' as usize] = Some(3);
+    slots
+}
+
+use bio_utils::sam::Op;
+use mito_check::alignment::{walk, Event};
+use mito_check::stats::{beta_binomial_upper_tail, binomial_upper_tail};
+// Flags of unmapped, secondary, QC-fail, and duplicate alignments.
+const SKIP_FLAGS: u32 = 0x4 | 0x100 | 0x200 | 0x400;
+fn register_alignments<I: std::iter::Iterator<Item = String>>(
+    lines: I,
+    pileups: &mut HashMap<String, Pileup>,
+    args: &Args,
+) {
+    for line in lines.filter(|l| !l.starts_with('@')) {
+        let sam = match bio_utils::sam::Sam::new(&line) {
+            Some(res) => res,
+            None => continue,
+        };
+        let fields: Vec<_> = line.split('\t').collect();
+        let flag: u32 = fields[1].parse().unwrap_or(SKIP_FLAGS);
+        let mapq: u8 = fields[4].parse().unwrap_or(0);
+        if flag & SKIP_FLAGS != 0 || mapq < args.min_mapq || fields[9] == "*" {
+            continue;
+        }
+        let pileup = match pileups.get_mut(sam.r_name()) {
+            Some(res) => res,
+            None => continue,
+        };
+        let strand = (flag & 0x10 != 0) as usize;
+        let seq = fields[9].as_bytes();
+        let qual = fields[10].as_bytes();
+        let is_good = |qpos: usize| match qual {
+            b"*" => true,
+            _ => qual
+                .get(qpos)
+                .is_some_and(|&q| args.min_base_quality + 33 <= q),
+        };
+        // Records running past the end of the reference (e.g., circular references) are truncated.
+        // SEQ is in the reference strand. Soft clipped bases are outside of the aligned region.
+        let cigar = sam.cigar();
+        let soft_clip = |op: Option<&Op>| match op {
+            Some(Op::SoftClip(l)) => *l,
+            _ => 0,
+        };
+        let is_hard_clip = |op: &&Op| matches!(op, Op::HardClip(_));
+        let head = soft_clip(cigar.iter().find(|op| !is_hard_clip(op)));
+        let tail = soft_clip(cigar.iter().rev().find(|op| !is_hard_clip(op)));
+        let aligned = (head, seq.len().saturating_sub(tail));
+        for column in walk(&cigar, sam.get_range().0, aligned, true) {
+            let rpos = column.rpos;
+            match column.event {
+                Event::Match | Event::Mismatch => {
+                    for (i, qpos) in (column.qstart..column.qend).enumerate() {
+                        let idx = seq.get(qpos).and_then(|&b| BASE2IDX[b as usize]);
+                        if let (Some(idx), true, Some(slot)) =
+                            (idx, is_good(qpos), pileup.bases.get_mut(rpos + i))
+                        {
+                            slot[idx][strand] += 1;
+                        }
+                    }
+                }
+                Event::Ins => {
+                    let (qstart, qend) = (column.qstart, column.qend);
+                    let inserted = seq.get(qstart..qend).map(|x| x.to_ascii_uppercase());
+                    let is_good = (qstart..qend).all(is_good);
+                    if let (Some(inserted), true, Some(slot)) =
+                        (inserted, is_good, pileup.indels.get_mut(rpos))
+                    {
+                        slot.entry(Indel::Ins(inserted)).or_default()[strand] += 1;
+                    }
+                }
+                Event::Del => {
+                    if let Some(slot) = pileup.indels.get_mut(rpos) {
+                        slot.entry(Indel::Del(column.len)).or_default()[strand] += 1;
+                    }
+                    for count in pileup.deleted.iter_mut().skip(rpos).take(column.len) {
+                        count[strand] += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Call {
+    // 0-based position of the VCF record.
+    position: usize,
+    refr: Vec<u8>,
+    alt: Vec<u8>,
+    depth: usize,
+    fraction: f64,
+    dp4: [usize; 4],
+    p_value: f64,
+}
+
+fn call_variants(refr: &[u8], pileup: &Pileup, args: &Args) -> Vec<Call> {
+    let mut calls = vec![];
+    for (pos, counts) in pileup.bases.iter().enumerate() {
+        let depth = pileup.depth(pos);
+        let ref_idx = match BASE2IDX[refr[pos] as usize] {
+            Some(idx) => idx,
+            None => continue,
+        };
+        if depth < args.min_depth {
+            continue;
+        }
+        let ref_counts = counts[ref_idx];
+        for (idx, alt_counts) in counts.iter().enumerate().filter(|&(i, _)| i != ref_idx) {
+            let alt = vec![b"ACGT"[idx]];
+            let refr = vec![refr[pos].to_ascii_uppercase()];
+            calls.extend(test(pos, (refr, alt), depth, ref_counts, *alt_counts, args));
+        }
+        // Indels are reported at the preceding base, with the reference counts of that base.
+        let anchor = match pos.checked_sub(1) {
+            Some(anchor) => anchor,
+            None => continue,
+        };
+        let ref_counts = match BASE2IDX[refr[anchor] as usize] {
+            Some(idx) => pileup.bases[anchor][idx],
+            None => [0, 0],
+        };
+        for (indel, alt_counts) in pileup.indels[pos].iter() {
+            let (r, a) = match indel {
+                Indel::Del(len) if pos + len <= refr.len() => {
+                    (refr[anchor..pos + len].to_vec(), refr[anchor..pos].to_vec())
+                }
+                Indel::Del(_) => continue,
+                Indel::Ins(seq) => {
+                    let mut alt = vec![refr[anchor]];
+                    alt.extend(seq);
+                    (vec![refr[anchor]], alt)
+                }
+            };
+            let (r, a) = (r.to_ascii_uppercase(), a.to_ascii_uppercase());
+            calls.extend(test(anchor, (r, a), depth, ref_counts, *alt_counts, args));
+        }
+    }
+    calls
+}
+
+fn test(
+    position: usize,
+    (refr, alt): (Vec<u8>, Vec<u8>),
+    depth: usize,
+    ref_counts: [usize; 2],
+    alt_counts: [usize; 2],
+    args: &Args,
+) -> Option<Call> {
+    let count = alt_counts[0] + alt_counts[1];
+    let fraction = count as f64 / depth as f64;
+    if fraction < args.min_allele_fraction {
+        return None;
+    }
+    let p_value = match 0f64 < args.overdispersion {
+        true => beta_binomial_upper_tail(count, depth, args.error_rate, args.overdispersion),
+        false => binomial_upper_tail(count, depth, args.error_rate),
+    };
+    (p_value <= args.max_p_value).then_some(Call {
+        position,
+        refr,
+        alt,
+        depth,
+        fraction,
+        dp4: [ref_counts[0], ref_counts[1], alt_counts[0], alt_counts[1]],
+        p_value,
+    })
+}
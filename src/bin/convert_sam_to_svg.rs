@@ -14,76 +14,421 @@ struct Args {
     /// Output path
     #[arg(short, long)]
     output: PathBuf,
-    // To re-calculate the alignment again.
-    // #[arg(short, long)]
-    // re_align: bool,
+    /// Layout and colours of the figure in TOML. Missing keys take the default values.
+    #[arg(long)]
+    theme: Option<PathBuf>,
+    /// Width of the figure (pixel). Overrides the theme.
+    #[arg(long)]
+    width: Option<usize>,
+    /// Height of each row of reads (pixel). Overrides the theme.
+    #[arg(long)]
+    height_per_reads: Option<usize>,
+    /// Interval of the ticks (bp). Overrides the theme. Chosen from the length of each region by default.
+    #[arg(long)]
+    tick: Option<usize>,
+    /// Also write the pileup as a PNG image.
+    #[arg(long)]
+    png: Option<PathBuf>,
+    /// Zoom factor of the PNG image relative to the SVG.
+    #[arg(long, default_value_t = 1.0)]
+    png_zoom: f32,
+    /// Also write a self-contained HTML viewer with pan/zoom and tooltips on reads.
+    #[arg(long)]
+    html: Option<PathBuf>,
+    /// To re-calculate the alignment again.
+    #[arg(short, long)]
+    re_align: bool,
+    /// Radius of the guided alignment in re-alignment.
+    #[arg(long, default_value_t = 200)]
+    radius: usize,
+    /// Match, mismatch, gap open, and gap extension scores in re-alignment.
+    #[arg(
+        long,
+        num_args = 4,
+        value_delimiter = ',',
+        allow_hyphen_values = true,
+        default_values_t = [5, -30, -35, -1]
+    )]
+    scoring: Vec<i32>,
+    /// Write the re-aligned alignments in the SAM file format.
+    #[arg(long, requires = "re_align")]
+    polished: Option<PathBuf>,
     /// To squish matches/deletions smaller than LEN.
     #[arg(short, long, default_value_t = 7)]
     squish: usize,
-    /// Target region ex. chr1:10000-1500000.
+    /// Target region ex. chr1:10000-1500000, or a contig name. Each region is drawn in its own panel.
     #[arg(short, long)]
-    target: Option<String>,
+    target: Vec<String>,
+    /// Target regions in BED format.
+    #[arg(long)]
+    regions: Option<PathBuf>,
+    /// Draw insertions longer than or equal to LEN.
+    #[arg(long, default_value_t = 10)]
+    min_insertion: usize,
+    /// Window to count clipped reads and large deletions in the coverage track.
+    #[arg(long, default_value_t = 100, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    window_size: usize,
+    /// Count clippings longer than or equal to LEN in the coverage track.
+    #[arg(long, default_value_t = 100)]
+    min_clip: usize,
+    /// Count deletions longer than or equal to LEN in the coverage track.
+    #[arg(long, default_value_t = 100)]
+    min_deletion: usize,
+    /// Group reads by KEY. Groups are drawn in separate blocks of rows.
+    #[arg(long, value_enum)]
+    group_by: Option<ReadKey>,
+    /// Colour reads by KEY.
+    #[arg(long, value_enum)]
+    color_by: Option<ReadKey>,
+    /// SAM tag to group or colour reads by `tag`, e.g., HP.
+    #[arg(long, default_value = "HP")]
+    tag: String,
+    /// Discard reads with mapping quality below this value, both from the rows and the coverage track.
+    #[arg(long, default_value_t = 0)]
+    min_mapq: u8,
+    /// Maximum number of rows in each region (each group if reads are grouped).
+    #[arg(long)]
+    max_rows: Option<usize>,
+    /// Draw only this fraction of reads in each region, chosen at random. The coverage track still counts all reads passing `--min-mapq`.
+    #[arg(long)]
+    downsample: Option<f64>,
+    /// Seed of the downsampling.
+    #[arg(long, default_value_t = 42)]
+    seed: u64,
+    /// Annotations in GFF3 format, drawn as feature tracks.
+    #[arg(long, num_args = 1..)]
+    gff: Vec<PathBuf>,
+    /// Feature types drawn from the GFF3 files.
+    #[arg(long, num_args = 1.., default_values_t = ["gene".to_string()])]
+    gff_types: Vec<String>,
+    /// Intervals in BED format, drawn as feature tracks.
+    #[arg(long, num_args = 1..)]
+    bed: Vec<PathBuf>,
+    /// Outputs of `annotate_repetitive_kmers`, drawn as feature tracks.
+    #[arg(long, num_args = 1..)]
+    repeats: Vec<PathBuf>,
+}
+
+/// Keys to group and colour reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ReadKey {
+    /// The strand of the alignment.
+    Strand,
+    /// The mapping quality. Reads with higher MAPQ come first.
+    Mapq,
+    /// The value of the SAM tag given by `--tag`.
+    Tag,
+    /// Whether the head and the tail of the read are clipped (longer than `--min-clip`) or not.
+    Clip,
 }
 
 use std::io::{BufRead, BufReader};
 
 fn main() -> std::io::Result<()> {
     let args = Args::parse();
+    let theme = load_theme(&args)?;
     let contigs = bio_utils::fasta::parse_into_vec(&args.contigs)?;
-    let selection = match &args.target {
-        Some(res) => Selection::new(res),
-        None => Selection::from_contigs(&contigs),
+    let selection = match (args.target.is_empty(), &args.regions) {
+        (true, None) => Selection::from_contigs(&contigs),
+        _ => Selection::new(&args.target, args.regions.as_deref(), &contigs)?,
     };
-    let (_, mut samfile) = if args.alignments.as_os_str() == "-" {
+    let (header, mut samfile) = if args.alignments.as_os_str() == "-" {
         let stdin = std::io::stdin();
         let stdin = BufReader::new(stdin.lock()).lines().filter_map(|l| l.ok());
-        parse_sam_file(stdin)
+        parse_sam_file(stdin)?
     } else {
         let file = std::fs::File::open(&args.alignments).map(BufReader::new)?;
         let file = file.lines().filter_map(|l| l.ok());
-        parse_sam_file(file)
+        parse_sam_file(file)?
     };
-    samfile.retain(|record| selection.is_in(record.r_name(), record.get_range()));
+    samfile.retain(|(record, _)| selection.is_in(record.r_name(), record.get_range()));
     let contigs: HashMap<String, _> = contigs
         .into_iter()
         .filter(|r| selection.has_contig(r.id()))
         .map(|r| (r.id().to_string(), r))
         .collect();
-    let tidy_alignments = convert_tidy(samfile, &contigs, &args);
-    flush_alignments(&tidy_alignments, &selection, &args);
-    Ok(())
+    if args.re_align {
+        samfile = re_align(samfile, &contigs, &args);
+    }
+    if let Some(path) = &args.polished {
+        use std::io::Write;
+        let mut wtr = std::fs::File::create(path).map(std::io::BufWriter::new)?;
+        for line in header.iter().chain(samfile.iter().map(|x| &x.1)) {
+            writeln!(&mut wtr, "{line}")?;
+        }
+    }
+    let (panels, coverages): (Vec<_>, Vec<_>) = selection
+        .selects
+        .iter()
+        .map(|region| {
+            let alns: Vec<_> = samfile
+                .iter()
+                .filter(|(aln, _)| region.overlaps(aln.r_name(), aln.get_range()))
+                .collect();
+            let (alns, coverage) = convert_tidy(alns, &contigs, &theme, &args);
+            ((region, alns), coverage)
+        })
+        .unzip();
+    let tracks = load_tracks(&theme, &args)?;
+    flush_alignments(&panels, &coverages, &tracks, &theme, &args)
+}
+
+// A feature track. Features are (contig, start, end, strand, label), 0-based and half-open.
+#[derive(Debug, Clone)]
+struct Track {
+    name: String,
+    color: String,
+    features: Vec<(String, usize, usize, Option<bool>, String)>,
+}
+
+// Blank, comment, `track`, and `browser` lines of a BED file carry no interval.
+fn is_bed_header(line: &str) -> bool {
+    line.trim().is_empty()
+        || line.starts_with('#')
+        || line.starts_with("track")
+        || line.starts_with("browser")
+}
+
+fn load_tracks(theme: &Theme, args: &Args) -> std::io::Result<Vec<Track>> {
+    let mut tracks = vec![];
+    for path in args.gff.iter() {
+        let features = mito_check::gff::parse_into_vec(path)?
+            .into_iter()
+            .filter(|f| args.gff_types.contains(&f.feature_type))
+            .map(|f| {
+                let label = f.name().unwrap_or("").to_string();
+                (f.seqid, f.start.saturating_sub(1), f.end, f.strand, label)
+            })
+            .collect();
+        let (name, color) = (mito_check::strain_name(path), theme.gene_color.clone());
+        tracks.push(Track {
+            name,
+            color,
+            features,
+        });
+    }
+    for path in args.bed.iter() {
+        let features = read_lines(path)?
+            .iter()
+            .filter(|l| !is_bed_header(l))
+            .filter_map(|line| {
+                let fields: Vec<_> = line.split('\t').collect();
+                let start: usize = fields.get(1)?.trim().parse().ok()?;
+                let end: usize = fields.get(2)?.trim().parse().ok()?;
+                let label = fields.get(3).unwrap_or(&"").to_string();
+                let strand = match fields.get(5) {
+                    Some(&"+") => Some(true),
+                    Some(&"-") => Some(false),
+                    _ => None,
+                };
+                Some((fields[0].to_string(), start, end, strand, label))
+            })
+            .collect();
+        let (name, color) = (mito_check::strain_name(path), theme.bed_color.clone());
+        tracks.push(Track {
+            name,
+            color,
+            features,
+        });
+    }
+    for path in args.repeats.iter() {
+        let features = mito_check::load_repeats(path)?
+            .into_iter()
+            .map(|(contig, start, end, count)| (contig, start, end, None, format!("x{count}")))
+            .collect();
+        let (name, color) = (mito_check::strain_name(path), theme.repeat_color.clone());
+        tracks.push(Track {
+            name,
+            color,
+            features,
+        });
+    }
+    Ok(tracks)
+}
+
+fn read_lines(path: &std::path::Path) -> std::io::Result<Vec<String>> {
+    let lines = std::fs::File::open(path)
+        .map(BufReader::new)?
+        .lines()
+        .filter_map(|l| l.ok())
+        .collect();
+    Ok(lines)
+}
+
+impl Track {
+    // Features on the contig are drawn as arrows (or boxes if the strand is unknown) between `top` and `top + theme.feature_height`.
+    fn to_svg(&self, contig: &str, scale: &Scale, top: f64, theme: &Theme) -> element::Group {
+        let mut group = element::Group::new()
+            .set("fill", self.color.as_str())
+            .set("stroke", "none");
+        let name = element::Text::new()
+            .add(svg::node::Text::new(self.name.as_str()))
+            .set("x", 5)
+            .set("y", top + theme.feature_height as f64)
+            .set("font-size", theme.feature_font_size)
+            .set("fill", theme.text_color.as_str());
+        group = group.add(name);
+        let features = self
+            .features
+            .iter()
+            .filter(|f| f.0 == contig && f.1 < scale.end && scale.start < f.2);
+        let (y_top, y_bottom) = (
+            top + theme.feature_font_size as f64,
+            top + theme.feature_height as f64,
+        );
+        let y_middle = (y_top + y_bottom) / 2f64;
+        for (_, start, end, strand, label) in features {
+            let (x_start, x_end) = (scale.map(*start), scale.map(*end));
+            let head = theme.arrow_head.min(x_end - x_start);
+            let shape = match strand {
+                Some(true) => Data::new()
+                    .move_to((x_start, y_top))
+                    .line_to((x_end - head, y_top))
+                    .line_to((x_end, y_middle))
+                    .line_to((x_end - head, y_bottom))
+                    .line_to((x_start, y_bottom)),
+                Some(false) => Data::new()
+                    .move_to((x_end, y_top))
+                    .line_to((x_start + head, y_top))
+                    .line_to((x_start, y_middle))
+                    .line_to((x_start + head, y_bottom))
+                    .line_to((x_end, y_bottom)),
+                None => Data::new()
+                    .move_to((x_start, y_top))
+                    .line_to((x_end, y_top))
+                    .line_to((x_end, y_bottom))
+                    .line_to((x_start, y_bottom)),
+            };
+            let tooltip = format!("{label} {}-{}", start + 1, end);
+            let shape = element::Path::new()
+                .set("d", shape.close())
+                .add(element::Title::new().add(svg::node::Text::new(tooltip)));
+            group = group.add(shape);
+            if !label.is_empty() {
+                let text = element::Text::new()
+                    .add(svg::node::Text::new(label.as_str()))
+                    .set("x", (x_start + x_end) / 2f64)
+                    .set("y", y_top - 1f64)
+                    .set("font-size", theme.feature_font_size)
+                    .set("text-anchor", "middle")
+                    .set("fill", theme.text_color.as_str());
+                group = group.add(text);
+            }
+        }
+        group
+    }
 }
 
 #[derive(Debug, Clone)]
 struct Selection {
-    selects: Vec<(String, usize, usize)>,
+    selects: Vec<Region>,
+}
+
+// A region drawn in a panel. 0-based, half-open.
+#[derive(Debug, Clone)]
+struct Region {
+    name: String,
+    contig: String,
+    start: usize,
+    end: usize,
+}
+
+impl Region {
+    fn overlaps(&self, id: &str, (start, end): (usize, usize)) -> bool {
+        id == self.contig && start < self.end && self.start < end
+    }
+}
+
+fn invalid_input(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, msg)
 }
 
 impl Selection {
     fn has_contig(&self, id: &str) -> bool {
-        self.selects.iter().any(|x| x.0 == id)
-    }
-    fn new(select: &str) -> Self {
-        let (name, range) = select.split_once(':').unwrap();
-        let (start, end) = range.split_once('-').unwrap();
-        let start: usize = start.parse().unwrap();
-        let end: usize = end.parse().unwrap();
-        let select = (name.to_string(), start, end);
-        Self {
-            selects: vec![select],
+        self.selects.iter().any(|x| x.contig == id)
+    }
+    // Targets are `contig:start-end` (1-based, inclusive) or `contig`.
+    fn new(
+        targets: &[String],
+        bed: Option<&std::path::Path>,
+        contigs: &[bio_utils::fasta::Record],
+    ) -> std::io::Result<Self> {
+        let lengths: HashMap<_, _> = contigs.iter().map(|c| (c.id(), c.seq().len())).collect();
+        let region = |contig: &str, start: usize, end: usize, name: String| {
+            let length = match lengths.get(contig) {
+                Some(&length) => length,
+                None => return Err(invalid_input(format!("{contig} is not in the contigs."))),
+            };
+            if end <= start || length < end {
+                let msg = format!("{name} is out of {contig} (length {length}).");
+                return Err(invalid_input(msg));
+            }
+            let contig = contig.to_string();
+            Ok(Region {
+                name,
+                contig,
+                start,
+                end,
+            })
+        };
+        let mut selects = vec![];
+        for target in targets.iter() {
+            let range = target
+                .rsplit_once(':')
+                .and_then(|(contig, range)| Some((contig, range.split_once('-')?)));
+            let select = match range {
+                Some((contig, (start, end))) => {
+                    let parse = |x: &str| x.replace(',', "").parse::<usize>().ok();
+                    match (parse(start), parse(end)) {
+                        (Some(start), Some(end)) if 0 < start => {
+                            region(contig, start - 1, end, target.clone())?
+                        }
+                        _ => return Err(invalid_input(format!("Malformed target:{target}"))),
+                    }
+                }
+                None => {
+                    let length = lengths.get(target.as_str()).copied().unwrap_or(0);
+                    region(target, 0, length, target.clone())?
+                }
+            };
+            selects.push(select);
         }
+        if let Some(path) = bed {
+            let file = std::fs::File::open(path).map(BufReader::new)?;
+            for line in file.lines().filter_map(|l| l.ok()) {
+                if is_bed_header(&line) {
+                    continue;
+                }
+                let fields: Vec<_> = line.split('\t').collect();
+                let start = fields.get(1).and_then(|x| x.trim().parse::<usize>().ok());
+                let end = fields.get(2).and_then(|x| x.trim().parse::<usize>().ok());
+                let (start, end) = match (start, end) {
+                    (Some(start), Some(end)) => (start, end),
+                    _ => return Err(invalid_input(format!("Malformed BED line:{line}"))),
+                };
+                let name = match fields.get(3) {
+                    Some(name) => name.to_string(),
+                    None => format!("{}:{}-{end}", fields[0], start + 1),
+                };
+                selects.push(region(fields[0], start, end, name)?);
+            }
+        }
+        Ok(Self { selects })
     }
     fn from_contigs(contigs: &[bio_utils::fasta::Record]) -> Self {
         let selects: Vec<_> = contigs
             .iter()
-            .map(|contig| (contig.id().to_string(), 0, contig.seq().len()))
+            .map(|contig| Region {
+                name: contig.id().to_string(),
+                contig: contig.id().to_string(),
+                start: 0,
+                end: contig.seq().len(),
+            })
             .collect();
         Self { selects }
     }
-    fn is_in(&self, id: &str, (start, end): (usize, usize)) -> bool {
-        self.selects
-            .iter()
-            .any(|&(ref ref_id, s, e)| id == ref_id && s <= start && end <= e)
+    fn is_in(&self, id: &str, range: (usize, usize)) -> bool {
+        self.selects.iter().any(|region| region.overlaps(id, range))
     }
 }
 
@@ -99,46 +444,180 @@ struct TidyAlignments {
     clips: (usize, usize),
     contig: String,
     read_id: String,
+    // Read base aligned to each reference position in [start, end). `-` for deletions.
+    bases: Vec<u8>,
+    // Reference position and the read base of mismatches.
+    mismatches: Vec<(usize, u8)>,
+    // Reference position and the length of insertions.
+    insertions: Vec<(usize, usize)>,
+    // Reference position of deletions longer than or equal to `--min-deletion`, before squishing.
+    large_deletions: Vec<usize>,
+    // Reference intervals of the matches before squishing, to compute the depth.
+    matches: Vec<(usize, usize)>,
+    is_forward: bool,
+    // Summary of the CIGAR, shown in the HTML viewer.
+    cigar: String,
+    mapq: u8,
+    // The value of the tag given by `--tag`.
+    tag: Option<String>,
+    // The group and the colour of the read.
+    group: String,
+    color: String,
 }
 
+impl TidyAlignments {
+    fn class(&self, key: ReadKey, args: &Args) -> String {
+        match key {
+            ReadKey::Strand if self.is_forward => "+".to_string(),
+            ReadKey::Strand => "-".to_string(),
+            ReadKey::Mapq => format!("MAPQ:{}", self.mapq),
+            ReadKey::Tag => {
+                let value = self.tag.as_deref().unwrap_or("NA");
+                format!("{}:{value}", args.tag)
+            }
+            ReadKey::Clip => {
+                let (head, tail) = self.clips;
+                match (args.min_clip <= head, args.min_clip <= tail) {
+                    (true, true) => "BothClipped".to_string(),
+                    (true, false) => "HeadClipped".to_string(),
+                    (false, true) => "TailClipped".to_string(),
+                    (false, false) => "Unclipped".to_string(),
+                }
+            }
+        }
+    }
+    // Classes are sorted by this key, so that reads with higher MAPQ come first.
+    fn order(&self, key: ReadKey, args: &Args) -> (std::cmp::Reverse<u8>, String) {
+        let mapq = if key == ReadKey::Mapq { self.mapq } else { 0 };
+        (std::cmp::Reverse(mapq), self.class(key, args))
+    }
+}
+
+// Return the alignments to be drawn, and the coverage of all the alignments.
 fn convert_tidy(
-    mut alignments: Vec<sam::Sam>,
+    mut alignments: Vec<&(sam::Sam, String)>,
     contigs: &HashMap<String, bio_utils::fasta::Record>,
+    theme: &Theme,
     args: &Args,
-) -> Vec<(String, Vec<TidyAlignments>)> {
-    let mut tidyalignments: HashMap<String, Vec<_>> =
-        contigs.keys().cloned().map(|k| (k, vec![])).collect();
+) -> (Vec<TidyAlignments>, Coverage) {
     // Sort the alignments by the start position.
-    alignments.sort_by_cached_key(|aln| {
+    alignments.sort_by_cached_key(|(aln, _)| {
         let range = aln.get_range();
         (aln.ref_name().to_string(), range)
     });
-    // Contig ID -> Row Number -> Rightmost position.
-    let mut row_fronteir: HashMap<String, Vec<usize>> =
-        contigs.keys().cloned().map(|k| (k, vec![])).collect();
-    for aln in alignments.iter() {
-        let mut tidied = to_tidy_alignments(aln, contigs, args);
-        let fronteir = row_fronteir.get_mut(&tidied.contig).unwrap();
-        let row = fronteir.iter().position(|&n| n <= tidied.start);
-        if let Some(row) = row {
-            tidied.row = row;
-            fronteir[row] = tidied.end;
-        } else {
-            tidied.row = fronteir.len();
-            fronteir.push(tidied.end);
+    let mut tidied: Vec<_> = alignments
+        .iter()
+        .map(|(aln, line)| to_tidy_alignments(aln, line, contigs, theme, args))
+        .filter(|aln| args.min_mapq <= aln.mapq)
+        .collect();
+    // The coverage is computed before downsampling and truncation.
+    let coverage = Coverage::new(&tidied, args);
+    if let Some(key) = args.group_by {
+        tidied
+            .iter_mut()
+            .for_each(|aln| aln.group = aln.class(key, args));
+    }
+    if let Some(key) = args.color_by {
+        let mut classes: Vec<_> = tidied.iter().map(|aln| aln.order(key, args)).collect();
+        classes.sort();
+        classes.dedup();
+        for aln in tidied.iter_mut() {
+            let class = aln.order(key, args);
+            let idx = classes.binary_search(&class).unwrap();
+            aln.color = theme.palette[idx % theme.palette.len()].clone();
+        }
+    }
+    // Records of the same read on the same contig are placed in the same row if they do not overlap.
+    let mut reads: HashMap<_, Vec<_>> = HashMap::new();
+    for (i, aln) in tidied.iter().enumerate() {
+        reads
+            .entry((&aln.contig, &aln.read_id))
+            .or_default()
+            .push(i);
+    }
+    let mut units: Vec<Vec<usize>> = vec![];
+    for (_, records) in reads {
+        let disjoint = records
+            .windows(2)
+            .all(|w| tidied[w[0]].end <= tidied[w[1]].start);
+        match disjoint {
+            true => units.push(records),
+            false => units.extend(records.into_iter().map(|i| vec![i])),
         }
-        tidyalignments.get_mut(&tidied.contig).unwrap().push(tidied);
     }
-    tidyalignments.into_iter().collect()
+    units.sort_by_key(|unit| {
+        let head = &tidied[unit[0]];
+        let group = args.group_by.map(|key| head.order(key, args));
+        (group, head.start, head.read_id.clone())
+    });
+    if let Some(fraction) = args.downsample {
+        use rand::Rng;
+        use rand::SeedableRng;
+        let mut rng: rand_xoshiro::Xoshiro256PlusPlus = SeedableRng::seed_from_u64(args.seed);
+        units.retain(|_| rng.gen_bool(fraction.clamp(0f64, 1f64)));
+    }
+    // Rows of each group are packed separately, with an empty row between groups.
+    let num_rows = |fronteir: &[usize]| {
+        args.max_rows
+            .map_or(fronteir.len(), |max| fronteir.len().min(max))
+    };
+    let mut rows: Vec<Option<usize>> = vec![None; tidied.len()];
+    let mut offset = 0;
+    // Row Number -> Rightmost position.
+    let mut fronteir: Vec<usize> = vec![];
+    let mut current_group = None;
+    for unit in units.iter() {
+        let (head, last) = (&tidied[unit[0]], &tidied[unit[unit.len() - 1]]);
+        if current_group != Some(&head.group) {
+            if current_group.is_some() {
+                offset += num_rows(&fronteir) + 1;
+            }
+            fronteir.clear();
+            current_group = Some(&head.group);
+        }
+        let (start, end) = (head.start, last.end);
+        let row = match fronteir.iter().position(|&n| n <= start) {
+            Some(row) => {
+                fronteir[row] = end;
+                row
+            }
+            None => {
+                fronteir.push(end);
+                fronteir.len() - 1
+            }
+        };
+        if row < args.max_rows.unwrap_or(usize::MAX) {
+            for &i in unit.iter() {
+                rows[i] = Some(offset + row);
+            }
+        }
+    }
+    let tidied = tidied
+        .into_iter()
+        .zip(rows)
+        .filter_map(|(mut aln, row)| {
+            aln.row = row?;
+            Some(aln)
+        })
+        .collect();
+    (tidied, coverage)
 }
 
 use bio_utils::sam;
 fn to_tidy_alignments(
     aln: &bio_utils::sam::Sam,
-    _contigs: &HashMap<String, bio_utils::fasta::Record>,
+    line: &str,
+    contigs: &HashMap<String, bio_utils::fasta::Record>,
+    theme: &Theme,
     args: &Args,
 ) -> TidyAlignments {
     let (start, end) = aln.get_range();
+    let fields: Vec<_> = line.split('\t').collect();
+    let tag = fields.iter().skip(11).find_map(|field| {
+        let (tag, value) = field.split_once(':')?;
+        let (_, value) = value.split_once(':')?;
+        (tag == args.tag).then(|| value.to_string())
+    });
     let mut cigar = aln.cigar();
     let tail_clip = match cigar.last().cloned() {
         Some(sam::Op::HardClip(l)) => {
@@ -156,12 +635,37 @@ fn to_tidy_alignments(
         }
         _ => 0,
     };
-    let clips = (head_clip, tail_clip);
-    // if args.re_align {
-    //     let contig = contigs.get(aln.ref_name()).unwrap();
-    //     cigar = re_align(aln, contig, &cigar);
-    // }
+    // Soft clippings are also clippings.
+    let soft_clip = |op: Option<&sam::Op>| match op {
+        Some(sam::Op::SoftClip(l)) => *l,
+        _ => 0,
+    };
+    let clips = (
+        head_clip + soft_clip(cigar.first()),
+        tail_clip + soft_clip(cigar.last()),
+    );
+    let (bases, mismatches, mut insertions) = align_bases(aln, &cigar, contigs.get(aln.r_name()));
+    insertions.retain(|&(_, len)| args.min_insertion <= len);
+    let summary = summarize_cigar(&cigar);
     let ops = to_op_runs(&cigar);
+    // (Reference position, length, operation)
+    let runs: Vec<_> = ops
+        .iter()
+        .scan(start, |rpos, &(len, op)| {
+            *rpos += len;
+            Some((*rpos - len, len, op))
+        })
+        .collect();
+    let large_deletions = runs
+        .iter()
+        .filter(|&&(_, len, op)| op == 'D' && args.min_deletion <= len)
+        .map(|&(rpos, _, _)| rpos)
+        .collect();
+    let matches = runs
+        .iter()
+        .filter(|&&(_, _, op)| op == 'M')
+        .map(|&(rpos, len, _)| (rpos, rpos + len))
+        .collect();
     let ops = compress_small_matches(&ops, args.squish);
     let ops = compress_small_deletions(&ops, args.squish);
     TidyAlignments {
@@ -172,92 +676,130 @@ fn to_tidy_alignments(
         clips,
         contig: aln.r_name().to_string(),
         read_id: aln.q_name().to_string(),
+        bases,
+        mismatches,
+        insertions,
+        large_deletions,
+        matches,
+        is_forward: aln.is_forward(),
+        cigar: summary,
+        mapq: fields.get(4).and_then(|x| x.parse().ok()).unwrap_or(0),
+        tag,
+        group: String::new(),
+        color: theme.read_color.clone(),
     }
 }
 
-// const RADIUS: usize = 200;
-// const PARAMS: (i32, i32, i32, i32) = (5, -30, -35, -1);
-
-// fn re_align(
-//     aln: &bio_utils::sam::Sam,
-//     contig: &bio_utils::fasta::Record,
-//     cigar: &[sam::Op],
-// ) -> Vec<sam::Op> {
-//     let seq = aln.seq();
-//     let len: usize = cigar
-//         .iter()
-//         .map(|op| match op {
-//             sam::Op::Match(l)
-//             | sam::Op::Mismatch(l)
-//             | sam::Op::Align(l)
-//             | sam::Op::Insertion(l) => *l,
-//             _ => 0,
-//         })
-//         .sum();
-//     assert_eq!(seq.len(), len);
-//     let (start, end) = aln.get_range();
-//     let contig = &contig.seq()[start..end];
-//     let kiley_op = to_kiley_ops(cigar);
-//     let kiley_op = if aln.is_forward() {
-//         let seq = seq.as_bytes();
-//         kiley::bialignment::guided::global_guided(contig, seq, &kiley_op, RADIUS, PARAMS).1
-//     } else {
-//         let seq = bio_utils::revcmp(seq.as_bytes());
-//         kiley::bialignment::guided::global_guided(contig, &seq, &kiley_op, RADIUS, PARAMS).1
-//     };
-//     to_sam_ops(&kiley_op)
-// }
-
-// fn to_kiley_ops(cigar: &[sam::Op]) -> Vec<kiley::Op> {
-//     cigar
-//         .iter()
-//         .flat_map(|op| match op {
-//             sam::Op::Match(l) | sam::Op::Mismatch(l) | sam::Op::Align(l) => {
-//                 std::iter::repeat(kiley::Op::Match).take(*l)
-//             }
-//             sam::Op::Deletion(l) => std::iter::repeat(kiley::Op::Del).take(*l),
-//             sam::Op::Insertion(l) => std::iter::repeat(kiley::Op::Ins).take(*l),
-//             _ => panic!("{:?}", op),
-//         })
-//         .collect()
-// }
-
-// fn to_sam_ops(k_ops: &[kiley::Op]) -> Vec<sam::Op> {
-//     if k_ops.is_empty() {
-//         return vec![];
-//     }
-//     fn is_the_same(op1: kiley::Op, op2: kiley::Op) -> bool {
-//         match (op1, op2) {
-//             (kiley::Op::Match, kiley::Op::Mismatch) | (kiley::Op::Mismatch, kiley::Op::Match) => {
-//                 true
-//             }
-//             (x, y) if x == y => true,
-//             _ => false,
-//         }
-//     }
-//     assert!(!k_ops.is_empty());
-//     let (mut current_op, mut len) = (k_ops[0], 1);
-//     let mut ops = vec![];
-//     for &op in k_ops.iter().skip(1) {
-//         if is_the_same(op, current_op) {
-//             len += 1;
-//         } else {
-//             match current_op {
-//                 kiley::Op::Del => ops.push(sam::Op::Deletion(len)),
-//                 kiley::Op::Ins => ops.push(sam::Op::Insertion(len)),
-//                 kiley::Op::Mismatch | kiley::Op::Match => ops.push(sam::Op::Match(len)),
-//             }
-//             current_op = op;
-//             len = 1;
-//         }
-//     }
-//     match current_op {
-//         kiley::Op::Del => ops.push(sam::Op::Deletion(len)),
-//         kiley::Op::Ins => ops.push(sam::Op::Insertion(len)),
-//         kiley::Op::Mismatch | kiley::Op::Match => ops.push(sam::Op::Match(len)),
-//     }
-//     ops
-// }
+// The number of aligned bases, and the number and the total length of insertions and deletions.
+fn summarize_cigar(cigar: &[sam::Op]) -> String {
+    let (mut aligned, mut ins, mut del) = (0, (0, 0), (0, 0));
+    for op in cigar.iter() {
+        match *op {
+            sam::Op::Align(l) | sam::Op::Match(l) | sam::Op::Mismatch(l) => aligned += l,
+            sam::Op::Insertion(l) => ins = (ins.0 + 1, ins.1 + l),
+            sam::Op::Deletion(l) => del = (del.0 + 1, del.1 + l),
+            _ => {}
+        }
+    }
+    format!(
+        "{aligned}M, {} ins ({}bp), {} del ({}bp)",
+        ins.0, ins.1, del.0, del.1
+    )
+}
+
+type AlignedBases = (Vec<u8>, Vec<(usize, u8)>, Vec<(usize, usize)>);
+// Read bases aligned to the reference, mismatches, and insertions. `M` operations are compared with the contig.
+fn align_bases(
+    aln: &bio_utils::sam::Sam,
+    cigar: &[sam::Op],
+    contig: Option<&bio_utils::fasta::Record>,
+) -> AlignedBases {
+    use mito_check::alignment::Event;
+    let (start, end) = aln.get_range();
+    let seq = match aln.seq() {
+        "*" => &[],
+        seq => seq.as_bytes(),
+    };
+    let head_clip = match cigar.first() {
+        Some(sam::Op::SoftClip(l)) => *l,
+        _ => 0,
+    };
+    let refr = contig.map(|contig| contig.seq());
+    let mut bases = Vec::with_capacity(end - start);
+    let (mut mismatches, mut insertions) = (vec![], vec![]);
+    for column in mito_check::alignment::walk(cigar, start, (head_clip, seq.len()), true) {
+        match column.event {
+            Event::Del => bases.extend(vec![b'-'; column.len]),
+            Event::Ins => insertions.push((column.rpos, column.len)),
+            Event::Match | Event::Mismatch => {
+                for column in column.split(true) {
+                    let base = seq.get(column.qstart).map(|b| b.to_ascii_uppercase());
+                    let ref_base = refr
+                        .and_then(|r| r.get(column.rpos))
+                        .map(|b| b.to_ascii_uppercase());
+                    let is_mismatch = match (column.event, base, ref_base) {
+                        (_, None, _) => false,
+                        (Event::Mismatch, _, _) => true,
+                        (_, Some(base), Some(ref_base)) => base != ref_base,
+                        _ => false,
+                    };
+                    let base = base.unwrap_or(b'N');
+                    if is_mismatch {
+                        mismatches.push((column.rpos, base));
+                    }
+                    bases.push(base);
+                }
+            }
+        }
+    }
+    (bases, mismatches, insertions)
+}
+
+// Re-align the records to the contigs, and replace their CIGARs.
+fn re_align(
+    samfile: Vec<(Sam, String)>,
+    contigs: &HashMap<String, bio_utils::fasta::Record>,
+    args: &Args,
+) -> Vec<(Sam, String)> {
+    let params = (
+        args.scoring[0],
+        args.scoring[1],
+        args.scoring[2],
+        args.scoring[3],
+    );
+    samfile
+        .into_iter()
+        .map(|(aln, line)| {
+            let contig = match contigs.get(aln.r_name()) {
+                Some(contig) => contig.seq(),
+                None => return (aln, line),
+            };
+            let (cigar, start) = mito_check::alignment::re_align(&aln, contig, args.radius, params);
+            let cigar = mito_check::alignment::to_cigar_string(&cigar);
+            let line = replace_cigar(&line, &cigar, start);
+            match Sam::new(&line) {
+                Some(polished) => (polished, line),
+                None => (aln, line),
+            }
+        })
+        .collect()
+}
+
+// Replace the CIGAR and the position (0-based `start`) of a SAM line. The MD and NM tags are removed as they are no longer valid.
+fn replace_cigar(line: &str, cigar: &str, start: usize) -> String {
+    let pos = (start + 1).to_string();
+    let fields: Vec<_> = line
+        .split('\t')
+        .enumerate()
+        .filter(|(i, field)| *i < 11 || !(field.starts_with("MD:") || field.starts_with("NM:")))
+        .map(|(i, field)| match i {
+            3 => pos.as_str(),
+            5 => cigar,
+            _ => field,
+        })
+        .collect();
+    fields.join("\t")
+}
 
 fn to_op_runs(cigar: &[sam::Op]) -> Vec<(usize, char)> {
     cigar
@@ -311,8 +853,8 @@ use svg::node::element;
 use svg::node::element::path::Data;
 
 impl TidyAlignments {
-    fn to_svg(&self, scale: &Scale, y_origin: usize) -> svg::node::element::Group {
-        let y_position = y_origin + self.row * HEIGHT_PER_READS;
+    fn to_svg(&self, scale: &Scale, y_origin: usize, theme: &Theme) -> svg::node::element::Group {
+        let y_position = y_origin + self.row * theme.height_per_reads;
         let start_pos_bp = self.start;
         let end_pos_bp = self.end;
         // Add main line.
@@ -321,10 +863,10 @@ impl TidyAlignments {
             .line_to((scale.map(end_pos_bp), y_position));
         let main_line = element::Path::new()
             .set("fill", "none")
-            .set("stroke", "skyblue")
+            .set("stroke", theme.deletion_color.as_str())
             .set("stroke-dasharray", 1)
-            .set("stroke-width", DEL_STROKE)
-            .set("stroke-opacity", DEL_OPACITY)
+            .set("stroke-width", theme.del_stroke)
+            .set("stroke-opacity", theme.del_opacity)
             .set("d", main_line);
         // Add clips.
         let clip_start = start_pos_bp.saturating_sub(self.clips.0);
@@ -333,9 +875,9 @@ impl TidyAlignments {
             .line_to((scale.map(start_pos_bp), y_position));
         let head_clip = element::Path::new()
             .set("fill", "none")
-            .set("stroke", "red")
-            .set("stroke-width", DEL_STROKE)
-            .set("stroke-opacity", CLIP_OPACITY)
+            .set("stroke", theme.clip_color.as_str())
+            .set("stroke-width", theme.del_stroke)
+            .set("stroke-opacity", theme.clip_opacity)
             .set("d", head_clip);
         let clip_end = end_pos_bp + self.clips.1;
         let tail_clip = Data::new()
@@ -343,13 +885,18 @@ impl TidyAlignments {
             .line_to((scale.map(clip_end), y_position));
         let tail_clip = element::Path::new()
             .set("fill", "none")
-            .set("stroke", "red")
-            .set("stroke-width", DEL_STROKE)
-            .set("stroke-opacity", CLIP_OPACITY)
+            .set("stroke", theme.clip_color.as_str())
+            .set("stroke-width", theme.del_stroke)
+            .set("stroke-opacity", theme.clip_opacity)
             .set("d", tail_clip);
-        // Add matches.
+        // Add matches. Individual bases are drawn on them if the view is zoomed in enough.
+        let draw_bases = theme.min_base_width <= scale.width(1);
+        let match_color = match draw_bases {
+            true => theme.base_background_color.as_str(),
+            false => self.color.as_str(),
+        };
         let mut match_rects = element::Group::new()
-            .set("fill", "black")
+            .set("fill", match_color)
             .set("stroke-width", 0);
         let mut rpos = start_pos_bp;
         for &(len, op) in self.operations.iter() {
@@ -358,12 +905,70 @@ impl TidyAlignments {
                 let rect = element::Rectangle::new()
                     .set("x", scale.map(rpos))
                     .set("width", width)
-                    .set("y", y_position - MATCH_HEIGHT / 2)
-                    .set("height", MATCH_HEIGHT);
+                    .set("y", y_position - theme.match_height / 2)
+                    .set("height", theme.match_height);
                 match_rects = match_rects.add(rect);
             }
             rpos += len;
         }
+        // Add mismatches, coloured by the read base.
+        let mut mismatches = element::Group::new().set("stroke-width", 0);
+        if !draw_bases {
+            for &(pos, base) in self.mismatches.iter() {
+                let rect = element::Rectangle::new()
+                    .set("x", scale.map(pos))
+                    .set("width", scale.width(1).max(theme.min_mismatch_width))
+                    .set("y", y_position - theme.match_height / 2)
+                    .set("height", theme.match_height)
+                    .set("fill", theme.base_color(base));
+                mismatches = mismatches.add(rect);
+            }
+        }
+        // Add bases. Matches are in the colour of the text, mismatches are coloured.
+        let mut bases = element::Group::new()
+            .set("font-size", theme.match_height)
+            .set("font-family", "monospace")
+            .set("text-anchor", "middle");
+        if draw_bases {
+            let positions = (self.start..).zip(self.bases.iter());
+            for (pos, &base) in positions.filter(|x| scale.start <= x.0 && x.0 < scale.end) {
+                let is_mismatch = self.mismatches.binary_search(&(pos, base)).is_ok();
+                let color = if is_mismatch {
+                    theme.base_color(base)
+                } else {
+                    theme.text_color.as_str()
+                };
+                let text = element::Text::new()
+                    .add(svg::node::Text::new((base as char).to_string()))
+                    .set("x", scale.map(pos) + scale.width(1) / 2f64)
+                    .set("y", y_position + theme.match_height / 2 - 1)
+                    .set("fill", color);
+                bases = bases.add(text);
+            }
+        }
+        // Add insertions.
+        let mut insertions = element::Group::new()
+            .set("fill", "none")
+            .set("stroke", theme.insertion_color.as_str())
+            .set("stroke-width", theme.ins_stroke);
+        for &(pos, len) in self.insertions.iter() {
+            let x = scale.map(pos);
+            let (top, bottom) = (
+                y_position - theme.height_per_reads / 2,
+                y_position + theme.height_per_reads / 2,
+            );
+            let marker = Data::new()
+                .move_to((x - 2f64, top))
+                .line_to((x + 2f64, top))
+                .move_to((x, top))
+                .line_to((x, bottom))
+                .move_to((x - 2f64, bottom))
+                .line_to((x + 2f64, bottom));
+            let marker = element::Path::new()
+                .set("d", marker)
+                .add(element::Title::new().add(svg::node::Text::new(format!("{len}bp"))));
+            insertions = insertions.add(marker);
+        }
         let id = svg::node::Value::from(self.read_id.as_str());
         svg::node::element::Group::new()
             .set("id", id)
@@ -371,64 +976,467 @@ impl TidyAlignments {
             .add(tail_clip)
             .add(head_clip)
             .add(match_rects)
+            .add(mismatches)
+            .add(bases)
+            .add(insertions)
+    }
+}
+
+// Load the theme from `--theme`, then override it by the flags.
+fn load_theme(args: &Args) -> std::io::Result<Theme> {
+    let mut theme = match &args.theme {
+        Some(path) => Theme::from_file(path)?,
+        None => Theme::default(),
+    };
+    if let Some(width) = args.width {
+        theme.width = width;
+    }
+    if let Some(height) = args.height_per_reads {
+        theme.height_per_reads = height;
+    }
+    if args.tick.is_some() {
+        theme.tick = args.tick;
+    }
+    if theme.width <= 2 * theme.side_margin || theme.palette.is_empty() {
+        let msg = "The width should be larger than the side margins, and the palette should not be empty.";
+        return Err(invalid_input(msg.to_string()));
+    }
+    Ok(theme)
+}
+
+// The smallest interval of 1, 2, or 5 times a power of ten yielding at most `max_ticks` ticks in `length` bp.
+fn nice_tick(length: usize, max_ticks: usize) -> usize {
+    let min_tick = length.div_ceil(max_ticks.max(1)).max(1);
+    let mut magnitude = 1;
+    loop {
+        if let Some(tick) = [1, 2, 5]
+            .map(|x| x * magnitude)
+            .into_iter()
+            .find(|&t| min_tick <= t)
+        {
+            return tick;
+        }
+        magnitude *= 10;
     }
 }
 
-const WIDTH: usize = 1500;
-const HEIGHT_PER_READS: usize = 10;
-const DEL_STROKE: usize = 3;
-// Should be even.
-const MATCH_HEIGHT: usize = 8;
-const DEL_OPACITY: f64 = 0.7;
-const CLIP_OPACITY: f64 = 0.3;
-const SCALE_MARGIN: usize = 20;
-const TOP_MARGIN: usize = 60;
-const PILEUP_MARGIN: usize = SCALE_MARGIN + TOP_MARGIN;
-const SIDE_MARGIN: usize = 50;
-const LABEL_OFFSET: usize = 75;
-const TICK_LEN: usize = 10;
-const TICK: usize = 50_000;
-const TICK_STROKE: usize = 2;
-const SCALE_STROKE: usize = 5;
-const SCALE_FONT_SIZE: usize = 25;
-const TICK_FONT_SIZE: usize = 20;
 fn flush_alignments(
-    tidy_alignments: &[(String, Vec<TidyAlignments>)],
-    selection: &Selection,
+    panels: &[(&Region, Vec<TidyAlignments>)],
+    coverages: &[Coverage],
+    tracks: &[Track],
+    theme: &Theme,
     args: &Args,
-) {
-    // for (id, alns) in tidy_alignments {
-    //     eprintln!("==========={id}===============");
-    //     for aln in alns.iter() {
-    //         eprintln!("{aln:?}");
-    //     }
-    // }
-    let scale = Scale::new(selection, args);
-    let acc_heights: Vec<usize> = tidy_alignments
+) -> std::io::Result<()> {
+    // Feature tracks are between the coverage track and the scale.
+    let tracks_height = tracks.len() * theme.feature_height;
+    let margin = theme.pileup_margin() + tracks_height;
+    let acc_heights: Vec<usize> = panels
         .iter()
         .map(|(_, alns)| {
             let max = alns.iter().map(|x| x.row).max().unwrap_or(0);
-            max * HEIGHT_PER_READS + PILEUP_MARGIN
+            max * theme.height_per_reads + margin
         })
-        .fold(vec![PILEUP_MARGIN], |mut acc, len| {
+        .fold(vec![margin], |mut acc, len| {
             acc.push(acc.last().unwrap() + len);
             acc
         });
     let height = *acc_heights.last().unwrap();
     let mut document = svg::Document::new()
-        .set("viewBox", (0, 0, WIDTH, height))
-        .set("width", WIDTH)
+        .set("viewBox", (0, 0, theme.width, height))
+        .set("width", theme.width)
         .set("height", height);
-    for (i, (id, alns)) in tidy_alignments.iter().enumerate() {
+    for (i, (region, alns)) in panels.iter().enumerate() {
         let y_origin = acc_heights[i];
+        let scale = Scale::new(region, theme);
         let mut group = svg::node::element::Group::new();
         for aln in alns.iter() {
-            group = group.add(aln.to_svg(&scale, y_origin));
+            group = group.add(aln.to_svg(&scale, y_origin, theme));
+        }
+        group = group.add(links_to_svg(alns, &scale, y_origin, theme));
+        group = group.add(group_labels(alns, y_origin, theme));
+        group = group.add(scale.to_svg(&region.name, y_origin, theme));
+        let tracks_bottom = y_origin - theme.scale_margin - theme.track_margin;
+        let coverage_bottom = (tracks_bottom - tracks_height) as f64;
+        let coverage = &coverages[i];
+        group = group.add(coverage_track(
+            coverage,
+            &scale,
+            coverage_bottom,
+            theme,
+            args,
+        ));
+        for (i, track) in tracks.iter().enumerate() {
+            let top = (tracks_bottom - tracks_height + i * theme.feature_height) as f64;
+            group = group.add(track.to_svg(&region.contig, &scale, top, theme));
         }
-        group = group.add(scale.to_svg(id, y_origin));
         document = document.add(group);
     }
-    svg::save(&args.output, &document).unwrap();
+    svg::save(&args.output, &document)?;
+    if let Some(path) = &args.png {
+        save_png(&document.to_string(), path, args.png_zoom)?;
+    }
+    if let Some(path) = &args.html {
+        save_html(&document.to_string(), panels, path)?;
+    }
+    Ok(())
+}
+
+// Rasterize the SVG. Texts are rendered with the system fonts.
+fn save_png(svg: &str, path: &std::path::Path, zoom: f32) -> std::io::Result<()> {
+    use resvg::{tiny_skia, usvg};
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    // The default families are Microsoft fonts. Without them, texts would be dropped.
+    let families: Vec<_> = options
+        .fontdb
+        .faces()
+        .flat_map(|face| face.families.iter().map(|(family, _)| family.clone()))
+        .collect();
+    let find = |candidates: &[&str]| {
+        candidates
+            .iter()
+            .find(|&&c| families.iter().any(|f| f == c))
+            .map(|c| c.to_string())
+            .or_else(|| families.first().cloned())
+    };
+    let has = |family: &str| families.iter().any(|f| f == family);
+    if !has("Times New Roman") {
+        if let Some(family) = find(&["DejaVu Serif", "Liberation Serif", "Noto Serif"]) {
+            options.fontdb_mut().set_serif_family(family.clone());
+            options.font_family = family;
+        }
+    }
+    if !has("Arial") {
+        if let Some(family) = find(&["DejaVu Sans", "Liberation Sans", "Noto Sans"]) {
+            options.fontdb_mut().set_sans_serif_family(family);
+        }
+    }
+    if !has("Courier New") {
+        if let Some(family) = find(&["DejaVu Sans Mono", "Liberation Mono", "Noto Sans Mono"]) {
+            options.fontdb_mut().set_monospace_family(family);
+        }
+    }
+    let tree = usvg::Tree::from_str(svg, &options).map_err(|e| invalid_input(e.to_string()))?;
+    let size = tree.size().to_int_size().scale_by(zoom);
+    let mut pixmap = size
+        .and_then(|size| tiny_skia::Pixmap::new(size.width(), size.height()))
+        .ok_or_else(|| invalid_input(format!("Invalid PNG zoom:{zoom}")))?;
+    pixmap.fill(tiny_skia::Color::WHITE);
+    let transform = tiny_skia::Transform::from_scale(zoom, zoom);
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+    pixmap.save_png(path).map_err(std::io::Error::other)
+}
+
+// Embed the SVG into an HTML page. Tooltips are looked up by the `id` of the group of each read.
+fn save_html(
+    svg: &str,
+    panels: &[(&Region, Vec<TidyAlignments>)],
+    path: &std::path::Path,
+) -> std::io::Result<()> {
+    // Read ID -> Records.
+    let mut records: HashMap<&str, Vec<String>> = HashMap::new();
+    for aln in panels.iter().flat_map(|(_, alns)| alns.iter()) {
+        let strand = if aln.is_forward { '+' } else { '-' };
+        let record = format!(
+            "{}:{}-{}({strand}) {} clips:{}/{}",
+            aln.contig, aln.start, aln.end, aln.cigar, aln.clips.0, aln.clips.1
+        );
+        records
+            .entry(aln.read_id.as_str())
+            .or_default()
+            .push(record);
+    }
+    let records: Vec<_> = records
+        .iter()
+        .map(|(id, records)| {
+            let records: Vec<_> = records.iter().map(|r| to_json_string(r)).collect();
+            format!("{}:[{}]", to_json_string(id), records.join(","))
+        })
+        .collect();
+    let html = HTML_TEMPLATE
+        .replace("{records}", &records.join(",\n"))
+        .replace("{svg}", svg);
+    std::fs::write(path, html)
+}
+
+fn to_json_string(x: &str) -> String {
+    let mut escaped = String::with_capacity(x.len() + 2);
+    escaped.push('"');
+    for c in x.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '<' => escaped.push_str("\\u003c"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// Scroll to zoom, drag to pan, and double-click to reset the view.
+const HTML_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Pileup</title>
+<style>
+html, body { margin: 0; height: 100%; }
+#viewer { width: 100%; height: 100%; overflow: hidden; cursor: grab; }
+#viewer svg { width: 100%; height: 100%; }
+#tooltip { position: absolute; display: none; padding: 4px 6px; background: #ffffe0;
+  border: 1px solid #888; font: 12px monospace; white-space: pre; pointer-events: none; }
+</style>
+</head>
+<body>
+<div id="viewer">
+{svg}
+</div>
+<div id="tooltip"></div>
+<script>
+const RECORDS = {
+{records}
+};
+const svg = document.querySelector("#viewer svg");
+const tooltip = document.getElementById("tooltip");
+svg.removeAttribute("width");
+svg.removeAttribute("height");
+const initial = svg.getAttribute("viewBox").split(/[\s,]+/).map(Number);
+let box = initial.slice();
+let anchor = null;
+function setBox() {
+  svg.setAttribute("viewBox", box.join(" "));
+}
+function toUser(e) {
+  const p = svg.createSVGPoint();
+  p.x = e.clientX;
+  p.y = e.clientY;
+  return p.matrixTransform(svg.getScreenCTM().inverse());
+}
+svg.addEventListener("wheel", (e) => {
+  e.preventDefault();
+  const p = toUser(e);
+  const f = e.deltaY < 0 ? 0.8 : 1.25;
+  box = [p.x - (p.x - box[0]) * f, p.y - (p.y - box[1]) * f, box[2] * f, box[3] * f];
+  setBox();
+}, { passive: false });
+svg.addEventListener("mousedown", (e) => { anchor = toUser(e); });
+window.addEventListener("mouseup", () => { anchor = null; });
+svg.addEventListener("dblclick", () => { box = initial.slice(); setBox(); });
+window.addEventListener("mousemove", (e) => {
+  if (anchor) {
+    const p = toUser(e);
+    box[0] -= p.x - anchor.x;
+    box[1] -= p.y - anchor.y;
+    setBox();
+    tooltip.style.display = "none";
+    return;
+  }
+  const group = e.target.closest ? e.target.closest("g[id]") : null;
+  const records = group ? RECORDS[group.id] : undefined;
+  if (!records) {
+    tooltip.style.display = "none";
+    return;
+  }
+  tooltip.textContent = [group.id].concat(records).join("\n");
+  tooltip.style.left = e.pageX + 12 + "px";
+  tooltip.style.top = e.pageY + 12 + "px";
+  tooltip.style.display = "block";
+});
+</script>
+</body>
+</html>
+"##;
+
+// Names of the groups of reads, at the first row of each group.
+fn group_labels(alns: &[TidyAlignments], y_origin: usize, theme: &Theme) -> element::Group {
+    let mut first_rows: HashMap<_, usize> = HashMap::new();
+    for aln in alns.iter().filter(|aln| !aln.group.is_empty()) {
+        let row = first_rows.entry(aln.group.as_str()).or_insert(aln.row);
+        *row = (*row).min(aln.row);
+    }
+    let mut labels = element::Group::new().set("font-size", theme.group_font_size);
+    for (group, row) in first_rows {
+        let label = element::Text::new()
+            .add(svg::node::Text::new(group))
+            .set("x", 5)
+            .set("y", y_origin + row * theme.height_per_reads)
+            .set("fill", theme.text_color.as_str());
+        labels = labels.add(label);
+    }
+    labels
+}
+
+// Connect consecutive records of the same read with dashed arcs, from the exit of a record to the entry of the next one.
+// Arcs are coloured by the orientation of the junction.
+fn links_to_svg(
+    alns: &[TidyAlignments],
+    scale: &Scale,
+    y_origin: usize,
+    theme: &Theme,
+) -> element::Group {
+    let mut reads: HashMap<_, Vec<_>> = HashMap::new();
+    for aln in alns.iter() {
+        reads.entry(aln.read_id.as_str()).or_default().push(aln);
+    }
+    let mut links = element::Group::new()
+        .set("fill", "none")
+        .set("stroke-width", theme.link_stroke)
+        .set("stroke-dasharray", 4);
+    for records in reads.values_mut().filter(|records| 1 < records.len()) {
+        // Order in the read.
+        records.sort_by_key(|aln| match aln.is_forward {
+            true => aln.clips.0,
+            false => aln.clips.1,
+        });
+        for pair in records.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            let exit = if from.is_forward {
+                from.end
+            } else {
+                from.start
+            };
+            let entry = if to.is_forward { to.start } else { to.end };
+            let color = match (from.is_forward, to.is_forward) {
+                (x, y) if x != y => theme.link_inversion_color.as_str(),
+                (true, _) if exit <= entry => theme.link_deletion_color.as_str(),
+                (false, _) if entry <= exit => theme.link_deletion_color.as_str(),
+                _ => theme.link_duplication_color.as_str(),
+            };
+            let y_from = (y_origin + from.row * theme.height_per_reads) as f64;
+            let y_to = (y_origin + to.row * theme.height_per_reads) as f64;
+            let (x_from, x_to) = (scale.map(exit), scale.map(entry));
+            let (x_control, y_control) =
+                ((x_from + x_to) / 2f64, y_from.min(y_to) - theme.link_height);
+            let arc = Data::new()
+                .move_to((x_from, y_from))
+                .quadratic_curve_to((x_control, y_control, x_to, y_to));
+            let arc = element::Path::new().set("stroke", color).set("d", arc);
+            links = links.add(arc);
+        }
+    }
+    links
+}
+
+// Matched intervals, clipped reads, and large deletions of the alignments in a region.
+#[derive(Debug, Clone, Default)]
+struct Coverage {
+    // Reference intervals of the matches, half-open.
+    matches: Vec<(usize, usize)>,
+    // Reference positions of the clipped ends.
+    clips: Vec<usize>,
+    // Reference positions of the large deletions.
+    deletions: Vec<usize>,
+}
+
+impl Coverage {
+    fn new(alns: &[TidyAlignments], args: &Args) -> Self {
+        let mut coverage = Self::default();
+        for aln in alns.iter() {
+            coverage.matches.extend(aln.matches.iter().copied());
+            coverage
+                .deletions
+                .extend(aln.large_deletions.iter().copied());
+            if args.min_clip <= aln.clips.0 {
+                coverage.clips.push(aln.start);
+            }
+            if args.min_clip <= aln.clips.1 {
+                coverage.clips.push(aln.end);
+            }
+        }
+        coverage
+    }
+}
+
+// Depth of the alignments, with the histograms of clipped reads (red) and large deletions (blue).
+// The depth is averaged in each pixel, and the histograms are counted in windows.
+fn coverage_track(
+    coverage: &Coverage,
+    scale: &Scale,
+    bottom: f64,
+    theme: &Theme,
+    args: &Args,
+) -> element::Group {
+    let (start, end) = (scale.start, scale.end);
+    let mut depth = vec![0i64; end - start + 1];
+    for &(s, e) in coverage.matches.iter() {
+        let (s, e) = (s.clamp(start, end), e.clamp(start, end));
+        depth[s - start] += 1;
+        depth[e - start] -= 1;
+    }
+    let depth: Vec<_> = depth
+        .iter()
+        .scan(0, |acc, x| {
+            *acc += x;
+            Some(*acc)
+        })
+        .take(end - start)
+        .collect();
+    let clips = mito_check::count_in_windows(coverage.clips.iter().copied(), args.window_size);
+    let deletions =
+        mito_check::count_in_windows(coverage.deletions.iter().copied(), args.window_size);
+    let max = depth
+        .iter()
+        .map(|&d| d as usize)
+        .chain(clips.values().copied())
+        .chain(deletions.values().copied())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let to_y = |count: f64| tidy(bottom - count / max as f64 * theme.track_height as f64);
+    // Depth.
+    let bin = ((end - start) as f64 / (theme.width - 2 * theme.side_margin) as f64).ceil() as usize;
+    let bin = bin.max(1);
+    let mut area = Data::new().move_to((scale.map(start), bottom));
+    for (i, chunk) in depth.chunks(bin).enumerate() {
+        let mean = chunk.iter().sum::<i64>() as f64 / chunk.len() as f64;
+        let pos = start + i * bin;
+        area = area
+            .line_to((scale.map(pos), to_y(mean)))
+            .line_to((scale.map(pos + chunk.len()), to_y(mean)));
+    }
+    let area = area.line_to((scale.map(end), bottom)).close();
+    let area = element::Path::new()
+        .set("fill", theme.depth_color.as_str())
+        .set("stroke", "none")
+        .set("d", area);
+    let mut track = element::Group::new().add(area);
+    // Histograms.
+    for (counts, color) in [
+        (&clips, theme.clip_color.as_str()),
+        (&deletions, theme.large_deletion_color.as_str()),
+    ] {
+        let mut bars = element::Group::new()
+            .set("fill", color)
+            .set("fill-opacity", theme.histogram_opacity)
+            .set("stroke-width", 0);
+        for (&window, &count) in counts.iter() {
+            let pos = window * args.window_size;
+            if pos < start || end <= pos {
+                continue;
+            }
+            let top = to_y(count as f64);
+            let rect = element::Rectangle::new()
+                .set("x", scale.map(pos))
+                .set(
+                    "width",
+                    scale.width(args.window_size).max(theme.min_mismatch_width),
+                )
+                .set("y", top)
+                .set("height", tidy(bottom - top));
+            bars = bars.add(rect);
+        }
+        track = track.add(bars);
+    }
+    let label = svg::node::Text::new(format!("Depth (max {max})"));
+    let label = element::Text::new()
+        .add(label)
+        .set("x", scale.map(start))
+        .set("y", bottom - theme.track_height as f64 - 5f64)
+        .set("font-size", theme.tick_font_size)
+        .set("fill", theme.text_color.as_str());
+    track.add(label)
 }
 
 #[derive(Debug, Clone)]
@@ -441,8 +1449,8 @@ struct Scale {
 }
 
 impl Scale {
-    fn to_svg(&self, id: &str, y_origin: usize) -> element::Group {
-        let y_position = y_origin - SCALE_MARGIN;
+    fn to_svg(&self, id: &str, y_origin: usize, theme: &Theme) -> element::Group {
+        let y_position = y_origin - theme.scale_margin;
         let id_node = svg::node::Value::from(id);
         let mut scale = svg::node::element::Group::new().set("id", id_node);
         let start = self.map(self.start);
@@ -452,40 +1460,47 @@ impl Scale {
             .line_to((end, y_position));
         let main_scale = element::Path::new()
             .set("fill", "none")
-            .set("stroke", "black")
-            .set("stroke-width", SCALE_STROKE)
+            .set("stroke", theme.text_color.as_str())
+            .set("stroke-width", theme.scale_stroke)
             .set("stroke-opacity", 1)
             .set("d", main_scale);
         scale = scale.add(main_scale);
         let label = svg::node::Text::new(id);
         let label = element::Text::new()
             .add(label)
-            .set("x", start + LABEL_OFFSET as f64)
-            .set("y", y_position - SCALE_STROKE - 5)
-            .set("font-size", SCALE_FONT_SIZE);
+            .set("x", start + theme.label_offset as f64)
+            .set("y", y_position - theme.scale_stroke - 5)
+            .set("font-size", theme.scale_font_size)
+            .set("fill", theme.text_color.as_str());
         scale = scale.add(label);
-        let tick_y_position = y_position - TICK_LEN;
-        let tic_pos = (0..)
-            .map(|i| i * TICK)
-            .skip_while(|&pos| pos < self.start)
-            .take_while(|&pos| pos <= self.end);
-        for pos in tic_pos {
+        let tick_y_position = y_position - theme.tick_len;
+        let interval = theme
+            .tick
+            .unwrap_or_else(|| nice_tick(self.end - self.start, theme.max_ticks))
+            .max(1);
+        let first_tick = self.start.div_ceil(interval) * interval;
+        for pos in (first_tick..=self.end).step_by(interval) {
             let tick = Data::new()
                 .move_to((self.map(pos), tick_y_position))
                 .line_to((self.map(pos), y_position));
             let tick = element::Path::new()
                 .set("fill", "none")
-                .set("stroke", "black")
-                .set("stroke-width", TICK_STROKE)
+                .set("stroke", theme.text_color.as_str())
+                .set("stroke-width", theme.tick_stroke)
                 .set("d", tick);
             scale = scale.add(tick);
-            let label = format!("{} Kbp", pos / 1_000);
+            let label = match interval {
+                1_000_000.. => format!("{} Mbp", pos as f64 / 1_000_000f64),
+                1_000.. => format!("{} Kbp", pos as f64 / 1_000f64),
+                _ => format!("{pos} bp"),
+            };
             let label = svg::node::Text::new(label);
             let label = element::Text::new()
                 .add(label)
                 .set("x", self.map(pos))
-                .set("y", y_position - SCALE_STROKE - 5)
-                .set("font-size", TICK_FONT_SIZE);
+                .set("y", y_position - theme.scale_stroke - 5)
+                .set("font-size", theme.tick_font_size)
+                .set("fill", theme.text_color.as_str());
             scale = scale.add(label);
         }
         scale
@@ -498,13 +1513,12 @@ impl Scale {
         let pos_in_pix = self.side_margin as f64 + (pos_in_bp - self.start) as f64 * self.scale;
         tidy(pos_in_pix)
     }
-    fn new(selection: &Selection, _args: &Args) -> Self {
-        let start = selection.selects.iter().map(|x| x.1).min().unwrap();
-        let end = selection.selects.iter().map(|x| x.2).max().unwrap();
+    fn new(region: &Region, theme: &Theme) -> Self {
+        let (start, end) = (region.start, region.end);
         let length = end - start;
-        let width = (WIDTH - SIDE_MARGIN * 2) as f64;
+        let width = (theme.width - theme.side_margin * 2) as f64;
         let scale = width / length as f64;
-        let side_margin = SIDE_MARGIN as f64;
+        let side_margin = theme.side_margin as f64;
         Self {
             start,
             end,
@@ -519,24 +1533,22 @@ fn tidy(x: f64) -> f64 {
 }
 
 use bio_utils::sam::Sam;
+use mito_check::theme::Theme;
 use std::collections::HashMap;
-type Samfile = (HashMap<String, usize>, Vec<Sam>);
-fn parse_sam_file<I: std::iter::Iterator<Item = String>>(lines: I) -> Samfile {
-    let mut contig_length = HashMap::new();
+// Header lines, and records with their lines.
+type Samfile = (Vec<String>, Vec<(Sam, String)>);
+fn parse_sam_file<I: std::iter::Iterator<Item = String>>(lines: I) -> std::io::Result<Samfile> {
+    let mut header = vec![];
     let mut alignments = vec![];
     for line in lines {
-        if line.starts_with("@SQ") {
-            let line: Vec<_> = line.split('\t').collect();
-            assert!(line[1].starts_with("SN:"));
-            let (_, tig_name) = line[1].split_once(':').unwrap();
-            assert!(line[2].starts_with("LN:"));
-            let (_, length) = line[2].split_once(':').unwrap();
-            let length: usize = length.parse().unwrap();
-            contig_length.insert(tig_name.to_string(), length);
-        } else if !line.starts_with('@') {
-            let sam = Sam::new(&line).unwrap();
-            alignments.push(sam);
+        if line.starts_with('@') {
+            header.push(line);
+        } else {
+            match Sam::new(&line) {
+                Some(sam) => alignments.push((sam, line)),
+                None => return Err(invalid_input(format!("Malformed SAM record:{line}"))),
+            }
         }
     }
-    (contig_length, alignments)
+    Ok((header, alignments))
 }
@@ -1,5 +1,7 @@
 use bio_utils::paf::PAF;
 use clap::Parser;
+use mito_check::stats::poisson_binomial;
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
@@ -14,6 +16,27 @@ struct Args {
     /// Upper limit of the insertion/deletion
     #[arg(short, long, default_value_t = 1000)]
     upper_size_of_indel: usize,
+    /// Report the probability that this many or more strains have errors at the same position.
+    #[arg(short, long, default_value_t = 2)]
+    min_errors: usize,
+    /// TSV file mapping contig names to strain names.
+    #[arg(long, group = "strains")]
+    strain_table: Option<PathBuf>,
+    /// FASTA files, one file per strain. The strain name is the file name up to the first dot.
+    #[arg(long, num_args = 1.., group = "strains")]
+    strain_fastas: Vec<PathBuf>,
+    /// Regular expression extracting the strain name from a contig name (the first capture group, or the whole match).
+    #[arg(long, group = "strains")]
+    strain_regex: Option<String>,
+    /// Reference in FASTA format. With `--queries`, alleles and sequence contexts are reported.
+    #[arg(short, long, requires = "queries")]
+    reference: Option<PathBuf>,
+    /// Query contigs in FASTA format.
+    #[arg(short, long, requires = "reference")]
+    queries: Option<PathBuf>,
+    /// Homopolymers shorter than this length are not regarded as homopolymer contexts.
+    #[arg(long, default_value_t = 3)]
+    min_homopolymer: usize,
 }
 fn main() -> std::io::Result<()> {
     let args = Args::parse();
@@ -25,119 +48,236 @@ fn main() -> std::io::Result<()> {
         .filter_map(|x| x.ok())
         .filter_map(|l| bio_utils::paf::PAF::new(&l))
         .collect();
+    let (references, queries) = match (&args.reference, &args.queries) {
+        (Some(refr), Some(query)) => (load_fasta(refr)?, load_fasta(query)?),
+        _ => (HashMap::new(), HashMap::new()),
+    };
+    let mut contexts = vec![];
     for record in records.iter() {
-        let mut qpos = 0;
-        let mut rpos = record.tstart;
         let qname = &record.qname;
         let rname = &record.tname;
-        let cigar = bio_utils::sam::parse_cigar_string(record.get_tag("cg").unwrap().1);
-        for op in cigar {
-            use bio_utils::sam::Op;
-            match op {
-                Op::Match(l) | Op::Align(l) => {
-                    qpos += l;
-                    rpos += l;
-                }
-                Op::Insertion(l) => {
-                    if record.relstrand {
-                        let qpos = record.qstart + qpos;
-                        diff.push((qname, qpos, rname, rpos, "Ins", l));
-                    } else {
-                        let qpos = record.qend - qpos;
-                        diff.push((qname, qpos, rname, rpos, "Ins", l));
-                    }
-                    qpos += l;
-                }
-                Op::Deletion(l) => {
-                    if record.relstrand {
-                        let qpos = record.qstart + qpos;
-                        diff.push((qname, qpos, rname, rpos, "Del", l));
-                    } else {
-                        let qpos = record.qend - qpos;
-                        diff.push((qname, qpos, rname, rpos, "Del", l));
-                    }
-                    rpos += l;
-                }
-                Op::Mismatch(l) => {
-                    if record.relstrand {
-                        let qpos = record.qstart + qpos;
-                        diff.push((qname, qpos, rname, rpos, "Mism", l));
-                    } else {
-                        let qpos = record.qend - qpos;
-                        diff.push((qname, qpos, rname, rpos, "Mism", l));
-                    }
-                    rpos += l;
-                    qpos += l;
-                }
-                _ => {}
+        let refr = references.get(rname.as_str()).map(|seq| seq.as_slice());
+        // The aligned region of the query, in the reference strand.
+        let query: Option<Vec<u8>> = queries.get(qname.as_str()).map(|seq| {
+            let seq = &seq[record.qstart..record.qend];
+            match record.relstrand {
+                true => seq.to_vec(),
+                false => bio_utils::revcmp(seq),
             }
+        });
+        for column in mito_check::alignment::walk_paf(record).unwrap() {
+            use mito_check::alignment::Event;
+            let t = match column.event {
+                Event::Match => continue,
+                Event::Ins => "Ins",
+                Event::Del => "Del",
+                Event::Mismatch => "Mism",
+            };
+            let (rpos, l) = (column.rpos, column.len);
+            diff.push((qname, column.qstart, rname, rpos, t, l));
+            let context = match (refr, query.as_deref()) {
+                (Some(refr), Some(query)) => annotate(
+                    refr,
+                    query,
+                    (rpos, column.offset),
+                    (t, l),
+                    args.min_homopolymer,
+                ),
+                _ => None,
+            };
+            contexts.push(context.unwrap_or_else(Context::unknown));
         }
     }
+    let strains = assign_strains(&records, &args)?;
     {
         let mut filename = args.prefix.clone();
         filename.push("diff.tsv");
         let mut wtr = std::fs::File::create(&filename).map(BufWriter::new)?;
-        writeln!(&mut wtr, "Query\tQpos\tRefr\tRpos\tType\tSize")?;
-        for (qname, qpos, rname, rpos, t, size) in diff.iter() {
-            writeln!(&mut wtr, "{qname}\t{qpos}\t{rname}\t{rpos}\t{t}\t{size}")?;
+        writeln!(
+            &mut wtr,
+            "Query\tQpos\tRefr\tRpos\tType\tSize\tRefAllele\tAltAllele\tContext\tUnit\tTractLen\tIndelClass\tStrain"
+        )?;
+        for ((qname, qpos, rname, rpos, t, size), ctx) in
+            std::iter::zip(diff.iter(), contexts.iter())
+        {
+            writeln!(
+                &mut wtr,
+                "{qname}\t{qpos}\t{rname}\t{rpos}\t{t}\t{size}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                ctx.refr,
+                ctx.alt,
+                ctx.context,
+                ctx.unit,
+                ctx.tract,
+                ctx.indel_class,
+                strains[*qname]
+            )?;
+        }
+    }
+    if args.reference.is_some() {
+        let total_length: usize = {
+            let contig_to_length: HashMap<_, _> = records
+                .iter()
+                .map(|paf| (paf.qname.as_str(), paf.qlen))
+                .collect();
+            contig_to_length.values().sum()
+        };
+        let mut summary: HashMap<_, (usize, usize)> = HashMap::new();
+        for (d, ctx) in std::iter::zip(diff.iter(), contexts.iter()) {
+            if d.5 < args.upper_size_of_indel {
+                let slot = summary
+                    .entry((ctx.context, d.4, ctx.indel_class))
+                    .or_default();
+                slot.0 += 1;
+                slot.1 += d.5;
+            }
+        }
+        let mut summary: Vec<_> = summary.into_iter().collect();
+        summary.sort();
+        let mut filename = args.prefix.clone();
+        filename.push("diff_context.tsv");
+        let mut wtr = std::fs::File::create(&filename).map(BufWriter::new)?;
+        writeln!(&mut wtr, "Context\tType\tIndelClass\tCount\tBases\tRate")?;
+        for ((context, t, class), (count, bases)) in summary {
+            let rate = bases as f64 / total_length as f64;
+            writeln!(
+                &mut wtr,
+                "{context}\t{t}\t{class}\t{count}\t{bases}\t{rate}"
+            )?;
         }
     }
-    let error_rates = get_error_profile(&records, &diff, args.upper_size_of_indel);
+    let error_rates = get_error_profile(&records, &diff, args.upper_size_of_indel, |contig| {
+        strains[contig].as_str()
+    });
     {
-        let mut filename = args.prefix;
+        let mut filename = args.prefix.clone();
         filename.push("diff_rate.tsv");
         let mut wtr = std::fs::File::create(&filename).map(BufWriter::new)?;
         for (cid, ins, del, mism, len) in error_rates.iter() {
             writeln!(&mut wtr, "{cid}\t{ins}\t{del}\t{mism}\t{len}")?;
         }
     }
-    let no_error: f64 = error_rates
-        .iter()
-        .map(|(_, _, _, mism, _)| (1f64 - mism))
-        .product();
-    let single_error: f64 = error_rates
-        .iter()
-        .enumerate()
-        .map(|(i, &(_, _, _, mism, _))| {
-            let no_error: f64 = error_rates
+    {
+        let contig_rates =
+            get_error_profile(&records, &diff, args.upper_size_of_indel, |contig| contig);
+        let mut filename = args.prefix.clone();
+        filename.push("diff_rate_contig.tsv");
+        let mut wtr = std::fs::File::create(&filename).map(BufWriter::new)?;
+        for (cid, ins, del, mism, len) in contig_rates.iter() {
+            let strain = &strains[cid];
+            writeln!(&mut wtr, "{cid}\t{strain}\t{ins}\t{del}\t{mism}\t{len}")?;
+        }
+    }
+    // Distributions of the number of strains with errors at a position, for each error class.
+    let distributions: Vec<(&str, Vec<f64>)> = {
+        let ins: Vec<_> = error_rates.iter().map(|x| x.1).collect();
+        let del: Vec<_> = error_rates.iter().map(|x| x.2).collect();
+        let mism: Vec<_> = error_rates.iter().map(|x| x.3).collect();
+        let total: Vec<_> = error_rates
+            .iter()
+            .map(|x| (x.1 + x.2 + x.3).min(1f64))
+            .collect();
+        vec![
+            ("Ins", poisson_binomial(&ins)),
+            ("Del", poisson_binomial(&del)),
+            ("Mism", poisson_binomial(&mism)),
+            ("Total", poisson_binomial(&total)),
+        ]
+    };
+    {
+        let mut filename = args.prefix.clone();
+        filename.push("error_distribution.tsv");
+        let mut wtr = std::fs::File::create(&filename).map(BufWriter::new)?;
+        writeln!(&mut wtr, "NumErrors\tIns\tDel\tMism\tTotal")?;
+        for k in 0..=error_rates.len() {
+            let probs: Vec<_> = distributions
                 .iter()
-                .enumerate()
-                .filter_map(|(j, (_, _, _, m, _))| (i != j).then_some(1f64 - *m))
-                .product();
-            no_error * mism
-        })
-        .sum();
-    let two_or_more_error = 1f64 - no_error - single_error;
-    println!("{two_or_more_error}");
+                .map(|(_, dist)| dist[k].to_string())
+                .collect();
+            writeln!(&mut wtr, "{k}\t{}", probs.join("\t"))?;
+        }
+    }
+    for (class, dist) in distributions.iter() {
+        let at_least: f64 = dist.iter().skip(args.min_errors).sum();
+        println!("{class}\t{}\t{}", args.min_errors, at_least.min(1f64));
+    }
     Ok(())
 }
 
+// Contig -> Strain. Without any option, the strain is the prefix of the contig name before the first `_`.
+// Contigs not assigned to any strain are regarded as strains by themselves.
+fn assign_strains(alns: &[PAF], args: &Args) -> std::io::Result<HashMap<String, String>> {
+    use std::io::*;
+    let mut assignments: HashMap<String, String> = HashMap::new();
+    if let Some(path) = &args.strain_table {
+        for line in std::fs::File::open(path)
+            .map(BufReader::new)?
+            .lines()
+            .filter_map(|l| l.ok())
+        {
+            if let Some((contig, strain)) = line.split_once('\t') {
+                assignments.insert(contig.to_string(), strain.trim().to_string());
+            }
+        }
+    }
+    for path in args.strain_fastas.iter() {
+        let strain = mito_check::strain_name(path);
+        for record in bio_utils::fasta::parse_into_vec(path)? {
+            assignments.insert(record.id().to_string(), strain.clone());
+        }
+    }
+    let regex = match &args.strain_regex {
+        Some(regex) => match regex::Regex::new(regex) {
+            Ok(res) => Some(res),
+            Err(why) => return Err(Error::new(ErrorKind::InvalidInput, why.to_string())),
+        },
+        None => None,
+    };
+    let use_default = args.strain_table.is_none() && args.strain_fastas.is_empty();
+    for contig in alns.iter().map(|aln| &aln.qname) {
+        if assignments.contains_key(contig) {
+            continue;
+        }
+        let strain = match &regex {
+            Some(regex) => regex.captures(contig).and_then(|caps| {
+                let strain = caps.get(1).or_else(|| caps.get(0))?;
+                Some(strain.as_str())
+            }),
+            None if use_default => contig.split_once('_').map(|(strain, _)| strain),
+            None => None,
+        };
+        if strain.is_none() {
+            eprintln!("{contig} is not assigned to any strain.");
+        }
+        let strain = strain.unwrap_or(contig).to_string();
+        assignments.insert(contig.clone(), strain);
+    }
+    Ok(assignments)
+}
+
 type ErrorProfile = (String, f64, f64, f64, usize);
-fn get_error_profile(
-    alns: &[PAF],
-    diff: &[(&String, usize, &String, usize, &str, usize)],
+fn get_error_profile<'a, F: Fn(&'a str) -> &'a str>(
+    alns: &'a [PAF],
+    diff: &[(&'a String, usize, &String, usize, &str, usize)],
     max_indel: usize,
+    group_of: F,
 ) -> Vec<ErrorProfile> {
-    use std::collections::HashMap;
-    let strain_to_length: HashMap<_, _> = {
+    let group_to_length: HashMap<_, _> = {
         let contig_to_length: HashMap<_, _> = alns
             .iter()
-            .map(|paf| (paf.qname.to_string(), paf.qlen))
+            .map(|paf| (paf.qname.as_str(), paf.qlen))
             .collect();
-        let mut strain_to_length: HashMap<_, usize> = HashMap::new();
+        let mut group_to_length: HashMap<_, usize> = HashMap::new();
         for (contig, length) in contig_to_length {
-            let (strain, _) = contig.split_once('_').unwrap();
-            *strain_to_length.entry(strain.to_string()).or_default() += length;
+            *group_to_length.entry(group_of(contig)).or_default() += length;
         }
-        strain_to_length
+        group_to_length
     };
-    let mut strain_diffs: HashMap<_, _> = strain_to_length
+    let mut group_diffs: HashMap<_, _> = group_to_length
         .keys()
-        .map(|st| (st.clone(), (0, 0, 0)))
+        .map(|&group| (group, (0, 0, 0)))
         .collect();
     for (qname, _, _, _, err, size) in diff.iter().filter(|x| x.5 < max_indel) {
-        let (strain, _) = qname.split_once('_').unwrap();
-        let slot = strain_diffs.get_mut(strain).unwrap();
+        let slot = group_diffs.get_mut(group_of(qname.as_str())).unwrap();
         match *err {
             "Ins" => slot.0 += size,
             "Del" => slot.1 += size,
@@ -145,14 +285,189 @@ fn get_error_profile(
             _ => panic!(),
         }
     }
-    strain_diffs
+    group_diffs
         .into_iter()
-        .map(|(strain, (ins, del, mism))| {
-            let length = strain_to_length[&strain];
+        .map(|(group, (ins, del, mism))| {
+            let length = group_to_length[group];
             let ins = ins as f64 / length as f64;
             let del = del as f64 / length as f64;
             let mism = mism as f64 / length as f64;
-            (strain, ins, del, mism, length)
+            (group.to_string(), ins, del, mism, length)
         })
         .collect()
 }
+
+// Sequences are uppercased, as soft-masked bases should be compared with unmasked ones.
+fn load_fasta(path: &std::path::Path) -> std::io::Result<HashMap<String, Vec<u8>>> {
+    let records = bio_utils::fasta::parse_into_vec(path)?;
+    Ok(records
+        .into_iter()
+        .map(|r| (r.id().to_string(), r.seq().to_ascii_uppercase()))
+        .collect())
+}
+
+// Longest repeat unit examined for tandem repeats.
+const MAX_UNIT: usize = 6;
+
+// Alleles and the sequence context of a difference. `.` if the sequences are not available.
+#[derive(Debug, Clone)]
+struct Context {
+    refr: String,
+    alt: String,
+    // Homopolymer, TandemRepeat, or Unique.
+    context: &'static str,
+    // Repeat unit and the length of the repeat tract in the reference.
+    unit: String,
+    tract: usize,
+    // Expansion or Contraction of the repeat tract, for indels.
+    indel_class: &'static str,
+}
+
+impl Context {
+    fn unknown() -> Self {
+        Self {
+            refr: ".".to_string(),
+            alt: ".".to_string(),
+            context: ".",
+            unit: ".".to_string(),
+            tract: 0,
+            indel_class: ".",
+        }
+    }
+}
+
+// `refr` is the reference and `query` is the aligned region of the query in the reference strand.
+// `rpos` is the position in the reference, `qpos` is the offset in the aligned region.
+fn annotate(
+    refr: &[u8],
+    query: &[u8],
+    (rpos, qpos): (usize, usize),
+    (t, len): (&str, usize),
+    min_homopolymer: usize,
+) -> Option<Context> {
+    let ref_allele = match t {
+        "Ins" => &[][..],
+        _ => refr.get(rpos..rpos + len)?,
+    };
+    let alt_allele = match t {
+        "Del" => &[][..],
+        _ => query.get(qpos..qpos + len)?,
+    };
+    let to_allele = |xs: &[u8]| match xs.is_empty() {
+        true => "-".to_string(),
+        false => String::from_utf8_lossy(xs).to_string(),
+    };
+    let (context, unit, tract, indel_class) = match t {
+        "Mism" => {
+            let (context, unit, tract) = mismatch_context(refr, rpos, len, min_homopolymer);
+            (context, unit, tract, ".")
+        }
+        _ => indel_context(refr, rpos, t, ref_allele, alt_allele, min_homopolymer),
+    };
+    Some(Context {
+        refr: to_allele(ref_allele),
+        alt: to_allele(alt_allele),
+        context,
+        unit: to_allele(unit),
+        tract,
+        indel_class,
+    })
+}
+
+// A mismatch is in a homopolymer context if the flanking bases form a homopolymer,
+// and in a tandem repeat if the reference around it is a repeat of at least three copies.
+fn mismatch_context(
+    refr: &[u8],
+    rpos: usize,
+    len: usize,
+    min_homopolymer: usize,
+) -> (&'static str, &[u8], usize) {
+    let flanks = [rpos.checked_sub(1), Some(rpos + len)];
+    let homopolymer = flanks
+        .iter()
+        .filter_map(|&pos| pos.filter(|&pos| pos < refr.len()))
+        .map(|pos| (pos, tract_at(refr, pos, 1)))
+        .max_by_key(|x| x.1);
+    if let Some((pos, run)) = homopolymer.filter(|x| min_homopolymer <= x.1) {
+        return ("Homopolymer", &refr[pos..pos + 1], run);
+    }
+    for period in 2..=MAX_UNIT.min(refr.len().saturating_sub(rpos)) {
+        let tract = tract_at(refr, rpos, period);
+        if 3 * period <= tract {
+            return ("TandemRepeat", &refr[rpos..rpos + period], tract);
+        }
+    }
+    ("Unique", &[], 0)
+}
+
+// An indel is an expansion (contraction) of a repeat if the inserted (deleted) sequence is
+// a repeat of a unit which also flanks the indel in the reference.
+fn indel_context<'a>(
+    refr: &[u8],
+    rpos: usize,
+    t: &str,
+    ref_allele: &'a [u8],
+    alt_allele: &'a [u8],
+    min_homopolymer: usize,
+) -> (&'static str, &'a [u8], usize, &'static str) {
+    let (indel, end) = match t {
+        "Ins" => (alt_allele, rpos),
+        _ => (ref_allele, rpos + ref_allele.len()),
+    };
+    let unit = match smallest_period(indel) {
+        Some(period) if period <= MAX_UNIT => &indel[..period],
+        _ => return ("Unique", &[], 0, "."),
+    };
+    let flanking = copies_before(refr, rpos, unit) + copies_after(refr, end, unit);
+    if flanking == 0 {
+        return ("Unique", &[], 0, ".");
+    }
+    let tract = flanking * unit.len() + ref_allele.len();
+    let class = match t {
+        "Ins" => "Expansion",
+        _ => "Contraction",
+    };
+    let context = match unit.len() {
+        1 if min_homopolymer <= tract => "Homopolymer",
+        1 => "Unique",
+        _ => "TandemRepeat",
+    };
+    (context, unit, tract, class)
+}
+
+// Length of the longest tract with the given period containing `pos`.
+fn tract_at(seq: &[u8], pos: usize, period: usize) -> usize {
+    let mut start = pos;
+    while 0 < start && start - 1 + period < seq.len() && seq[start - 1] == seq[start - 1 + period] {
+        start -= 1;
+    }
+    let mut end = pos;
+    while end + period < seq.len() && seq[end] == seq[end + period] {
+        end += 1;
+    }
+    (end + period).min(seq.len()) - start
+}
+
+// The smallest period p such that `seq` is a repeat of `seq[..p]`.
+fn smallest_period(seq: &[u8]) -> Option<usize> {
+    (1..=seq.len()).find(|&p| {
+        let mut chunks = seq.chunks_exact(p);
+        chunks.remainder().is_empty() && chunks.all(|c| c == &seq[..p])
+    })
+}
+
+fn copies_before(seq: &[u8], pos: usize, unit: &[u8]) -> usize {
+    let mut end = pos;
+    while unit.len() <= end && &seq[end - unit.len()..end] == unit {
+        end -= unit.len();
+    }
+    (pos - end) / unit.len()
+}
+
+fn copies_after(seq: &[u8], pos: usize, unit: &[u8]) -> usize {
+    let mut start = pos;
+    while seq.get(start..start + unit.len()) == Some(unit) {
+        start += unit.len();
+    }
+    (start - pos) / unit.len()
+}
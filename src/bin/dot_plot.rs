@@ -0,0 +1,304 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+/// Draw a dot plot of two assemblies in SVG. The reference is on the x-axis, and the query is on the y-axis.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Alignments in PAF, MAF, or Last's TSV (`maf-convert tab`) format. The format is detected from the content.
+    /// In MAF, the first sequence of each block should be the reference.
+    #[arg(short, long)]
+    alignments: PathBuf,
+    /// Output path.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Repetitive regions from `annotate_repetitive_kmers`, shaded on both axes.
+    #[arg(short, long, num_args = 1..)]
+    repeats: Vec<PathBuf>,
+    /// Filter out alignments below this size.
+    #[arg(short, long, default_value_t = 1000)]
+    min_aln_size: usize,
+    /// Keep query contigs in the order of their length, instead of ordering them to match the reference.
+    #[arg(long)]
+    no_reorder: bool,
+    /// Width and height of the plot area (pixel).
+    #[arg(long, default_value_t = 1000)]
+    size: usize,
+    /// Theme in TOML format, shared with `convert_sam_to_svg`. Fields not given take the default values.
+    #[arg(long)]
+    theme: Option<PathBuf>,
+}
+
+use mito_check::pairwise::Block;
+use mito_check::theme::Theme;
+use mito_check::Repeat;
+use std::collections::HashMap;
+use std::io::*;
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+    let all_blocks = parse_alignments(&args.alignments)?;
+    // Contigs are taken from all the alignments, so that contigs without large alignments keep their places.
+    let mut refrs: HashMap<_, usize> = HashMap::new();
+    let mut queries: HashMap<_, usize> = HashMap::new();
+    for block in all_blocks.iter() {
+        refrs.insert(block.refr.as_str(), block.rlen);
+        queries.insert(block.query.as_str(), block.qlen);
+    }
+    let blocks: Vec<_> = all_blocks
+        .iter()
+        .filter(|b| args.min_aln_size <= (b.rend - b.rstart).min(b.qend - b.qstart))
+        .cloned()
+        .collect();
+    let refr_axis = Axis::new(by_length(refrs));
+    let queries = match args.no_reorder {
+        true => by_length(queries),
+        false => order_queries(queries, &blocks, &refr_axis),
+    };
+    let query_axis = Axis::new(queries);
+    let mut repeats = vec![];
+    for path in args.repeats.iter() {
+        repeats.extend(mito_check::load_repeats(path)?);
+    }
+    let theme = match &args.theme {
+        Some(path) => Theme::from_file(path)?,
+        None => Theme::default(),
+    };
+    let axes = (&refr_axis, &query_axis);
+    let document = draw(&blocks, axes, &repeats, &theme, &args);
+    svg::save(&args.output, &document)
+}
+
+fn parse_alignments(path: &std::path::Path) -> std::io::Result<Vec<Block>> {
+    let lines: Vec<_> = std::fs::File::open(path)
+        .map(BufReader::new)?
+        .lines()
+        .filter_map(|l| l.ok())
+        .filter(|l| !l.is_empty())
+        .collect();
+    let first = lines.iter().find(|l| !l.starts_with('#'));
+    let is_tab = first.is_some_and(|l| {
+        let fields: Vec<_> = l.split('\t').collect();
+        matches!(fields.get(9), Some(&"+") | Some(&"-"))
+    });
+    let blocks = if lines
+        .iter()
+        .any(|l| l.starts_with("##maf") || l.starts_with("a "))
+    {
+        bio_utils::maf::parse_into_vec(path)?
+            .iter()
+            .filter_map(Block::from_maf)
+            .collect()
+    } else if is_tab {
+        lines
+            .iter()
+            .filter(|l| !l.starts_with('#'))
+            .filter_map(|l| Block::from_tab(l))
+            .collect()
+    } else {
+        lines
+            .iter()
+            .filter_map(|l| bio_utils::paf::PAF::new(l))
+            .map(|paf| Block {
+                refr: paf.tname,
+                rstart: paf.tstart,
+                rend: paf.tend,
+                rlen: paf.tlen,
+                query: paf.qname,
+                qstart: paf.qstart,
+                qend: paf.qend,
+                qlen: paf.qlen,
+                is_forward: paf.relstrand,
+            })
+            .collect()
+    };
+    Ok(blocks)
+}
+
+fn by_length(contigs: HashMap<&str, usize>) -> Vec<(String, usize)> {
+    let mut contigs: Vec<_> = contigs
+        .into_iter()
+        .map(|(id, len)| (id.to_string(), len))
+        .collect();
+    contigs.sort_by(|x, y| y.1.cmp(&x.1).then(x.0.cmp(&y.0)));
+    contigs
+}
+
+// Order the query contigs by the mean reference position of their alignments, weighted by the alignment size.
+// Contigs without alignments come last.
+fn order_queries(
+    queries: HashMap<&str, usize>,
+    blocks: &[Block],
+    refr_axis: &Axis,
+) -> Vec<(String, usize)> {
+    // Query -> (Sum of position x size, Sum of size).
+    let mut anchors: HashMap<&str, (f64, f64)> = HashMap::new();
+    for block in blocks.iter() {
+        let offset = match refr_axis.offsets.get(&block.refr) {
+            Some(&offset) => offset,
+            None => continue,
+        };
+        let size = (block.rend - block.rstart) as f64;
+        let center = (offset + (block.rstart + block.rend) / 2) as f64;
+        let anchor = anchors.entry(block.query.as_str()).or_default();
+        anchor.0 += center * size;
+        anchor.1 += size;
+    }
+    let mut contigs = by_length(queries);
+    contigs.sort_by(|x, y| {
+        let position = |id: &str| match anchors.get(id) {
+            Some(&(sum, size)) if 0f64 < size => sum / size,
+            _ => f64::INFINITY,
+        };
+        position(&x.0).total_cmp(&position(&y.0))
+    });
+    contigs
+}
+
+// Contigs placed side by side on an axis.
+#[derive(Debug, Clone)]
+struct Axis {
+    contigs: Vec<(String, usize)>,
+    // Contig ID -> the position of its start on the axis.
+    offsets: HashMap<String, usize>,
+    total: usize,
+}
+
+impl Axis {
+    fn new(contigs: Vec<(String, usize)>) -> Self {
+        let mut offsets = HashMap::new();
+        let mut total = 0;
+        for (id, len) in contigs.iter() {
+            offsets.insert(id.clone(), total);
+            total += len;
+        }
+        Self {
+            contigs,
+            offsets,
+            total,
+        }
+    }
+    // The position in [0, 1] on the axis, if the contig is on the axis.
+    fn map(&self, id: &str, pos: usize) -> Option<f64> {
+        let offset = self.offsets.get(id)?;
+        Some((offset + pos) as f64 / self.total.max(1) as f64)
+    }
+}
+
+use svg::node::element;
+use svg::node::element::path::Data;
+fn draw(
+    blocks: &[Block],
+    (refr_axis, query_axis): (&Axis, &Axis),
+    repeats: &[Repeat],
+    theme: &Theme,
+    args: &Args,
+) -> svg::Document {
+    let size = args.size as f64;
+    let (left, top) = (theme.dot_margin as f64, theme.dot_margin as f64);
+    let (right, bottom) = (left + size, top + size);
+    let to_x = |id: &str, pos: usize| refr_axis.map(id, pos).map(|x| tidy(left + x * size));
+    let to_y = |id: &str, pos: usize| query_axis.map(id, pos).map(|y| tidy(bottom - y * size));
+    let total = args.size + 2 * theme.dot_margin;
+    let mut document = svg::Document::new()
+        .set("viewBox", (0, 0, total, total))
+        .set("width", total)
+        .set("height", total);
+    // Repeats.
+    let mut shades = element::Group::new()
+        .set("fill", theme.repeat_color.as_str())
+        .set("fill-opacity", theme.dot_repeat_opacity)
+        .set("stroke", "none");
+    for (id, start, end, _) in repeats.iter() {
+        if let (Some(x_start), Some(x_end)) = (to_x(id, *start), to_x(id, *end)) {
+            let rect = element::Rectangle::new()
+                .set("x", x_start)
+                .set("y", top)
+                .set("width", tidy(x_end - x_start))
+                .set("height", size);
+            shades = shades.add(rect);
+        }
+        if let (Some(y_start), Some(y_end)) = (to_y(id, *start), to_y(id, *end)) {
+            let rect = element::Rectangle::new()
+                .set("x", left)
+                .set("y", y_end)
+                .set("width", size)
+                .set("height", tidy(y_start - y_end));
+            shades = shades.add(rect);
+        }
+    }
+    document = document.add(shades);
+    // Separators and the names of the contigs.
+    let mut separators = element::Group::new()
+        .set("stroke", theme.dot_separator_color.as_str())
+        .set("stroke-width", theme.dot_separator_stroke);
+    let mut labels = element::Group::new()
+        .set("font-size", theme.dot_font_size)
+        .set("fill", theme.text_color.as_str());
+    for (id, len) in refr_axis.contigs.iter() {
+        let (start, end) = (to_x(id, 0).unwrap(), to_x(id, *len).unwrap());
+        if end < right {
+            let line = Data::new().move_to((end, top)).line_to((end, bottom));
+            separators = separators.add(element::Path::new().set("d", line));
+        }
+        let (x, y) = (
+            (start + end) / 2f64,
+            bottom + theme.dot_font_size as f64 + 5f64,
+        );
+        let label = element::Text::new()
+            .add(svg::node::Text::new(id.as_str()))
+            .set("transform", format!("rotate(30 {x} {y})"))
+            .set("x", x)
+            .set("y", y);
+        labels = labels.add(label);
+    }
+    for (id, len) in query_axis.contigs.iter() {
+        let (start, end) = (to_y(id, 0).unwrap(), to_y(id, *len).unwrap());
+        if top < end {
+            let line = Data::new().move_to((left, end)).line_to((right, end));
+            separators = separators.add(element::Path::new().set("d", line));
+        }
+        let label = element::Text::new()
+            .add(svg::node::Text::new(id.as_str()))
+            .set("x", left - 5f64)
+            .set("y", (start + end) / 2f64)
+            .set("text-anchor", "end");
+        labels = labels.add(label);
+    }
+    document = document.add(separators).add(labels);
+    // Alignments. Reverse blocks run from the top left to the bottom right.
+    let mut lines = element::Group::new()
+        .set("fill", "none")
+        .set("stroke-width", theme.dot_block_stroke)
+        .set("stroke-linecap", "round");
+    for block in blocks.iter() {
+        let (qstart, qend) = match block.is_forward {
+            true => (block.qstart, block.qend),
+            false => (block.qend, block.qstart),
+        };
+        let start = to_x(&block.refr, block.rstart).zip(to_y(&block.query, qstart));
+        let end = to_x(&block.refr, block.rend).zip(to_y(&block.query, qend));
+        if let (Some(start), Some(end)) = (start, end) {
+            let color = match block.is_forward {
+                true => theme.dot_forward_color.as_str(),
+                false => theme.dot_reverse_color.as_str(),
+            };
+            let line = Data::new().move_to(start).line_to(end);
+            lines = lines.add(element::Path::new().set("stroke", color).set("d", line));
+        }
+    }
+    // Frame.
+    let frame = element::Rectangle::new()
+        .set("x", left)
+        .set("y", top)
+        .set("width", size)
+        .set("height", size)
+        .set("fill", "none")
+        .set("stroke", theme.text_color.as_str())
+        .set("stroke-width", theme.dot_frame_stroke);
+    document.add(lines).add(frame)
+}
+
+fn tidy(x: f64) -> f64 {
+    (x * 1000f64).ceil() / 1000f64
+}
@@ -160,18 +160,16 @@ fn survey_break_points(
     min_size: usize,
     window_size: usize,
 ) -> HashMap<String, Vec<usize>> {
-    let mut num_of_stst_reads: HashMap<_, HashMap<_, usize>> = HashMap::new();
+    let mut positions: HashMap<_, Vec<_>> = HashMap::new();
     for (_, record) in records.iter() {
         let (start, stop) = record.get_range();
-        let (start, stop) = (start / window_size, stop / window_size);
         let ref_name = record.ref_name().to_string();
-        let slot = num_of_stst_reads.entry(ref_name).or_default();
-        *slot.entry(start).or_default() += 1;
-        *slot.entry(stop).or_default() += 1;
+        positions.entry(ref_name).or_default().extend([start, stop]);
     }
-    num_of_stst_reads
+    positions
         .into_iter()
-        .map(|(id, counts)| {
+        .map(|(id, positions)| {
+            let counts = mito_check::count_in_windows(positions, window_size);
             let mut positions: Vec<usize> = counts
                 .into_iter()
                 .filter_map(|(pos, count)| (min_size < count).then_some(window_size * pos))
@@ -14,17 +14,23 @@ struct Args {
     /// Alignments between the reference and the contigs.
     #[arg(short, long)]
     alignments: PathBuf,
-    /// Minimum crop range.
+    /// Variant sites closer than this are merged into the same window.
     #[arg(long, default_value_t = 20)]
-    min_crop_len: usize,
-    /// Maximum crop range.
+    merge_distance: usize,
+    /// Padding added to both ends of each window.
+    #[arg(long, default_value_t = 20)]
+    padding: usize,
+    /// Maximum span of the variant sites in a window (excluding padding).
     #[arg(long, default_value_t = 500)]
-    max_crop_len: usize,
+    max_width: usize,
     #[arg(long)]
     prefix: PathBuf,
     /// Remove mismatches [Length]-bp near the start/end of the contig.
     #[arg(long, default_value_t = 1000)]
     safe_margin: usize,
+    /// Radius of the guided alignment to the reference region.
+    #[arg(long, default_value_t = 50)]
+    radius: usize,
 }
 
 use std::collections::HashMap;
@@ -47,94 +53,165 @@ fn main() -> std::io::Result<()> {
     for crop_region in crop_regions.iter() {
         let (sid, (start, end)) = crop_region;
         let mut output = args.prefix.clone();
-        output.push(format!("{sid}_{start}_{end}.fa"));
+        output.push(format!("{sid}_{start}_{end}.mul.fa"));
         let mut wtr = std::fs::File::create(output).map(BufWriter::new)?;
-        let mut sequences: Vec<_> = reference
+        let (ref_id, ref_seq) = reference
             .iter()
             .find_map(|refr| {
                 if refr.id() == sid {
-                    let seq = String::from_utf8_lossy(&refr.seq()[*start..*end]).to_string();
                     let id = format!("{}_{start}_{end}", refr.id());
-                    Some(vec![(id, seq)])
+                    Some((id, &refr.seq()[*start..*end]))
                 } else {
                     None
                 }
             })
             .unwrap();
+        let mut crops = vec![];
         for contig in contigs.iter() {
             let alns = match alignments.get(contig.id()) {
                 Some(res) => res,
                 None => continue,
             };
-            sequences.extend(get_crop_region(contig, alns, crop_region));
+            crops.extend(get_crop_region(contig, alns, crop_region));
         }
-        for (id, seq) in sequences {
-            if !seq.is_empty() {
-                writeln!(&mut wtr, ">{id}\n{seq}")?;
-            }
+        crops.retain(|(_, seq, _)| !seq.is_empty());
+        let alignments: Vec<_> = crops
+            .into_iter()
+            .map(|(id, seq, guide)| {
+                let (_, ops) = global_guided(ref_seq, &seq, &guide, args.radius, PARAMS);
+                (id, seq, ops)
+            })
+            .collect();
+        for (id, seq) in pileup(&ref_id, ref_seq, &alignments) {
+            let seq = String::from_utf8_lossy(&seq);
+            writeln!(&mut wtr, ">{id}\n{seq}")?;
         }
     }
     Ok(())
 }
 
+// Match, mismatch, gap open, and gap extension.
+const PARAMS: (i32, i32, i32, i32) = (2, -4, -4, -2);
+
+use kiley::bialignment::guided::global_guided;
+type Columns = (Vec<Option<u8>>, Vec<Vec<u8>>);
+// Star alignment centered at the reference. Insertions after the same reference base are left-justified.
+fn pileup(
+    ref_id: &str,
+    refr: &[u8],
+    alignments: &[(String, Vec<u8>, Vec<kiley::Op>)],
+) -> Vec<(String, Vec<u8>)> {
+    // For each sequence, bases aligned to each reference base, and insertions before each reference base.
+    let columns: Vec<Columns> = alignments
+        .iter()
+        .map(|(_, seq, ops)| {
+            let mut bases = Vec::with_capacity(refr.len());
+            let mut insertions = vec![vec![]; refr.len() + 1];
+            let mut qpos = 0;
+            for op in ops.iter() {
+                match op {
+                    kiley::Op::Match | kiley::Op::Mismatch => {
+                        bases.push(Some(seq[qpos]));
+                        qpos += 1;
+                    }
+                    kiley::Op::Del => bases.push(None),
+                    kiley::Op::Ins => {
+                        insertions[bases.len()].push(seq[qpos]);
+                        qpos += 1;
+                    }
+                }
+            }
+            (bases, insertions)
+        })
+        .collect();
+    let max_ins: Vec<_> = (0..=refr.len())
+        .map(|i| {
+            columns
+                .iter()
+                .map(|(_, ins)| ins[i].len())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let mut ref_row = vec![];
+    for (i, &len) in max_ins.iter().enumerate() {
+        ref_row.extend(vec![b'-'; len]);
+        ref_row.extend(refr.get(i));
+    }
+    let mut rows = vec![(ref_id.to_string(), ref_row)];
+    for ((id, _, _), (bases, insertions)) in alignments.iter().zip(columns) {
+        let mut row = vec![];
+        for (i, &len) in max_ins.iter().enumerate() {
+            row.extend(insertions[i].iter());
+            row.extend(vec![b'-'; len - insertions[i].len()]);
+            if let Some(base) = bases.get(i) {
+                row.push(base.unwrap_or(b'-'));
+            }
+        }
+        rows.push((id.clone(), row));
+    }
+    rows
+}
+
 use bio_utils::paf::PAF;
 type Region = (String, (usize, usize));
 fn find_crop_regions(alignments: &HashMap<String, Vec<PAF>>, arg: &Args) -> Vec<Region> {
-    let position_of_variants = enumerate_mismatches(alignments, arg);
+    let position_of_variants = enumerate_variant_sites(alignments, arg);
+    let reference_lengths: HashMap<_, _> = alignments
+        .values()
+        .flat_map(|alns| alns.iter())
+        .map(|aln| (aln.tname.as_str(), aln.tlen))
+        .collect();
     position_of_variants
         .iter()
         .flat_map(|(id, poss)| {
-            let regions = aggregate(poss, arg);
+            let regions = aggregate(poss, reference_lengths[id.as_str()], arg);
             regions.into_iter().map(|range| (id.clone(), range))
         })
         .collect()
 }
 
-fn aggregate(poss: &[usize], args: &Args) -> Vec<(usize, usize)> {
-    if poss.is_empty() {
-        return Vec::new();
-    }
-    let mut start_pos = poss[0];
-    let mut current_pos = poss[0];
+// Aggregate sorted variant sites into windows (0-based, half-open), padded and clamped to the reference.
+fn aggregate(poss: &[usize], length: usize, args: &Args) -> Vec<(usize, usize)> {
+    let pad = |(start, end): (usize, usize)| {
+        let start = start.saturating_sub(args.padding);
+        let end = (end + 1 + args.padding).min(length);
+        (start, end)
+    };
     let mut ranges = vec![];
-    for &pos in poss.iter().skip(1) {
-        if args.min_crop_len < pos - current_pos || args.max_crop_len < current_pos - start_pos {
-            let range = (
-                start_pos.saturating_sub(args.min_crop_len),
-                current_pos + args.min_crop_len,
-            );
-            ranges.push(range);
-            start_pos = pos;
-            current_pos = pos;
-        } else {
-            current_pos = pos;
-        }
+    let mut window: Option<(usize, usize)> = None;
+    for &pos in poss.iter() {
+        window = match window {
+            Some((start, end))
+                if pos <= end + args.merge_distance && pos - start < args.max_width =>
+            {
+                Some((start, pos))
+            }
+            Some(window) => {
+                ranges.push(pad(window));
+                Some((pos, pos))
+            }
+            None => Some((pos, pos)),
+        };
     }
+    ranges.extend(window.map(pad));
     ranges
 }
 
-fn enumerate_mismatches(
+fn enumerate_variant_sites(
     alignments: &HashMap<String, Vec<PAF>>,
     arg: &Args,
 ) -> HashMap<String, Vec<usize>> {
-    let mut found_mismatches: HashMap<_, Vec<_>> = HashMap::new();
+    let mut found_sites: HashMap<_, Vec<_>> = HashMap::new();
     for aln in alignments.values().flat_map(|alns| alns.iter()) {
-        let mut rpos = aln.tstart;
-        let slot = found_mismatches.entry(aln.tname.clone()).or_default();
-        let cigar = bio_utils::sam::parse_cigar_string(aln.get_tag("cg").unwrap().1);
-        for op in cigar {
-            use bio_utils::sam::Op;
-            match op {
-                Op::Match(l) | Op::Align(l) => {
-                    rpos += l;
-                }
-                Op::Insertion(_) => {}
-                Op::Deletion(l) => rpos += l,
-                Op::Mismatch(l) => {
-                    slot.push(rpos);
-                    rpos += l;
-                }
-                _ => {}
+        let slot = found_sites.entry(aln.tname.clone()).or_default();
+        for column in walk_paf(aln).unwrap() {
+            match column.event {
+                Event::Match => {}
+                Event::Ins => slot.push(column.rpos),
+                Event::Mismatch => slot.extend(column.rpos..column.rend()),
+                // Only both ends of a deletion, so that a long deletion does not span many windows.
+                Event::Del => slot.extend([column.rpos, column.rend() - 1]),
             }
         }
     }
@@ -143,21 +220,22 @@ fn enumerate_mismatches(
         .flat_map(|alns| alns.iter())
         .map(|aln| (aln.tname.to_string(), aln.tlen))
         .collect();
-    for (contig_name, slot) in found_mismatches.iter_mut() {
+    for (contig_name, slot) in found_sites.iter_mut() {
         slot.sort_unstable();
         slot.dedup();
         let length = contig_lengths[contig_name];
         slot.retain(|&pos| arg.safe_margin < pos && pos < length.saturating_sub(arg.safe_margin));
     }
-    found_mismatches
+    found_sites
 }
 
 use bio_utils::fasta::Record;
+// Return the ID, the sequence oriented to the reference, and the alignment to the reference region as the guide.
 fn get_crop_region(
     contig: &Record,
     alns: &[PAF],
     (ref_id, range): &Region,
-) -> Vec<(String, String)> {
+) -> Vec<(String, Vec<u8>, Vec<kiley::Op>)> {
     let seq = contig.seq();
     let &(start, end) = range;
     let id = contig.id();
@@ -167,63 +245,51 @@ fn get_crop_region(
             if &aln.tname != ref_id || out_of_range {
                 None
             } else {
-                let (qstart, qend, direction) = crop(aln, start, end);
+                let (qstart, qend, direction, guide) = crop(aln, start, end);
                 let seq = match direction {
-                    true => String::from_utf8_lossy(&seq[qstart..qend]).to_string(),
-                    false => String::from_utf8(bio_utils::revcmp(&seq[qstart..qend])).unwrap(),
+                    true => seq[qstart..qend].to_vec(),
+                    false => bio_utils::revcmp(&seq[qstart..qend]),
                 };
                 let id = format!("{id}_{qstart}_{qend}_{direction}");
-                Some((id, seq))
+                Some((id, seq, guide))
             }
         })
         .collect()
 }
 
-fn crop(alignment: &PAF, start: usize, end: usize) -> (usize, usize, bool) {
+use mito_check::alignment::{walk_paf, Event};
+fn crop(alignment: &PAF, start: usize, end: usize) -> (usize, usize, bool, Vec<kiley::Op>) {
+    // Offsets in the aligned region of the query at `start` and `end` of the reference.
     let mut crop_start = None;
     let mut crop_end = None;
     let (mut rpos, mut qpos) = (alignment.tstart, 0);
-    let cigar = bio_utils::sam::parse_cigar_string(alignment.get_tag("cg").unwrap().1);
-    let mut ops = cigar.iter().flat_map(|op| {
-        use bio_utils::sam::Op;
-        let (op, len) = match op {
-            Op::Align(l) | Op::Mismatch(l) | Op::Match(l) => (b'M', *l),
-            Op::Insertion(l) | Op::SoftClip(l) | Op::HardClip(l) => (b'I', *l),
-            Op::Deletion(l) => (b'D', *l),
-            _ => panic!(),
-        };
-        std::iter::repeat(op).take(len)
-    });
-    while rpos < start {
-        match ops.next() {
-            Some(b'M') => {
-                rpos += 1;
-                qpos += 1
-            }
-            Some(b'D') => rpos += 1,
-            Some(b'I') => qpos += 1,
-            None => break,
-            _ => panic!(),
+    // The reference region before the alignment is deleted.
+    let mut guide = vec![kiley::Op::Del; rpos.saturating_sub(start)];
+    let columns = walk_paf(alignment).unwrap().into_iter();
+    for column in columns.flat_map(|c| c.split(alignment.relstrand)) {
+        if rpos == start {
+            crop_start.get_or_insert(qpos);
+        }
+        if rpos == end {
+            break;
         }
+        if start <= rpos {
+            guide.push(match column.event {
+                Event::Match | Event::Mismatch => kiley::Op::Match,
+                Event::Ins => kiley::Op::Ins,
+                Event::Del => kiley::Op::Del,
+            });
+        }
+        rpos = column.rend();
+        qpos = column.offset_end();
     }
     if rpos == start {
-        crop_start = Some(qpos);
-    }
-    while rpos < end {
-        match ops.next() {
-            Some(b'M') => {
-                rpos += 1;
-                qpos += 1
-            }
-            Some(b'D') => rpos += 1,
-            Some(b'I') => qpos += 1,
-            None => break,
-            _ => panic!(),
-        }
+        crop_start.get_or_insert(qpos);
     }
     if rpos == end {
         crop_end = Some(qpos);
     }
+    guide.extend(vec![kiley::Op::Del; end.saturating_sub(rpos)]);
     // eprintln!(
     //     "{}\t{}\t{crop_start:?}\t{crop_end:?}",
     //     alignment.qname, alignment.relstrand
@@ -237,7 +303,7 @@ fn crop(alignment: &PAF, start: usize, end: usize) -> (usize, usize, bool) {
             Some(res) => alignment.qstart + res,
             None => alignment.qend,
         };
-        (c_start, c_end, true)
+        (c_start, c_end, true, guide)
     } else {
         let c_start = match crop_end {
             Some(res) => alignment.qend - res,
@@ -247,6 +313,6 @@ fn crop(alignment: &PAF, start: usize, end: usize) -> (usize, usize, bool) {
             Some(res) => alignment.qend - res,
             None => alignment.qend,
         };
-        (c_start, c_end, false)
+        (c_start, c_end, false, guide)
     }
 }
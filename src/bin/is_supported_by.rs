@@ -7,15 +7,33 @@ struct Args {
     /// The pileup.
     #[arg(short, long)]
     pileup: PathBuf,
-    /// Minimum support for variants.
+    /// Minimum support of a non-reference allele to call the column as a variant.
     #[arg(short, long, default_value_t = 2)]
     min_support: usize,
+    /// Output per-column variant calls in TSV format into.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Minimum frequency of the alternative allele to be reported.
+    #[arg(long, default_value_t = 0f64)]
+    min_freq: f64,
+    /// Maximum frequency of the alternative allele to be reported.
+    #[arg(long, default_value_t = 1f64)]
+    max_freq: f64,
+    /// Minimum p-value of the strand bias test on the alternative allele (`_true`/`_false` suffix of the IDs).
+    /// The forward/reverse split of the alternative allele is tested against that of the whole column by a two-sided binomial test.
+    #[arg(long)]
+    min_strand_p: Option<f64>,
 }
 
 fn main() -> std::process::ExitCode {
     let args = Args::parse();
     let pileup = bio_utils::fasta::parse_into_vec(&args.pileup).unwrap();
-    let num_var = num_supported_variants(&pileup, args.min_support);
+    // The reference row is excluded from both the calls and the count.
+    let calls = call_variants(&pileup, &args);
+    if let Some(output) = &args.output {
+        flush_calls(output, &calls).unwrap();
+    }
+    let num_var = calls.len();
     if 0 < num_var {
         let sid = pileup[0].id();
         println!("{sid}\t{num_var}");
@@ -26,24 +44,154 @@ fn main() -> std::process::ExitCode {
 }
 
 use std::collections::HashMap;
-fn num_supported_variants(pileup: &[bio_utils::fasta::Record], min: usize) -> usize {
-    let len = pileup[0].seq().len();
-    let mut summary: Vec<HashMap<_, usize>> = vec![HashMap::new(); len];
-    for record in pileup.iter() {
-        for (i, &base) in record.seq().iter().enumerate() {
-            if base != b'-' {
-                *summary[i].entry(base).or_default() += 1;
-            }
+// Number of records supporting an allele, and those from each direction.
+#[derive(Debug, Clone, Copy, Default)]
+struct Support {
+    total: usize,
+    forward: usize,
+    reverse: usize,
+}
+
+// Direction of the cropped sequence, encoded as `_true`/`_false` suffix by `focus_on_mismatches`.
+fn direction(record: &bio_utils::fasta::Record) -> Option<bool> {
+    match record.id().rsplit_once('_') {
+        Some((_, "true")) => Some(true),
+        Some((_, "false")) => Some(false),
+        _ => None,
+    }
+}
+
+// Count alleles in the column, including gaps. Sorted by the counts in descending order.
+fn count_alleles(records: &[bio_utils::fasta::Record], column: usize) -> Vec<(u8, Support)> {
+    let mut counts: HashMap<u8, Support> = HashMap::new();
+    for record in records.iter() {
+        let base = record.seq()[column].to_ascii_uppercase();
+        let slot = counts.entry(base).or_default();
+        slot.total += 1;
+        match direction(record) {
+            Some(true) => slot.forward += 1,
+            Some(false) => slot.reverse += 1,
+            None => {}
+        }
+    }
+    let mut alleles: Vec<_> = counts.into_iter().collect();
+    alleles.sort_by(|x, y| y.1.total.cmp(&x.1.total).then(x.0.cmp(&y.0)));
+    alleles
+}
+
+// `column` is the sum of the supports of all alleles in the column.
+fn is_strand_balanced(support: &Support, column: &Support, args: &Args) -> bool {
+    let directed = support.forward + support.reverse;
+    let col_directed = column.forward + column.reverse;
+    match args.min_strand_p {
+        // If every read in the column comes from one direction, the allele can not be biased against it.
+        Some(min_p) if 0 < directed && 0 < column.forward && 0 < column.reverse => {
+            let p = column.forward as f64 / col_directed as f64;
+            min_p <= mito_check::stats::binomial_two_sided(support.forward, directed, p)
+        }
+        _ => true,
+    }
+}
+
+#[derive(Debug, Clone)]
+struct VariantCall {
+    column: usize,
+    refr: String,
+    // 0-based position of the reference. For insertions (gaps in the reference), the position of the next base.
+    position: usize,
+    ref_base: u8,
+    // Support of the reference allele.
+    ref_support: Support,
+    // The alternative allele, the most supported non-reference allele passing the filters.
+    alt: (u8, Support),
+    // Alleles and their supports, sorted by the counts in descending order. Gaps are alleles.
+    alleles: Vec<(u8, Support)>,
+    depth: usize,
+}
+
+// The first record is the reference, whose ID is [reference name]_[start]_[end].
+// Alleles are counted in the other records, and compared with the reference base.
+fn call_variants(pileup: &[bio_utils::fasta::Record], args: &Args) -> Vec<VariantCall> {
+    let (refr, start) = {
+        let mut fields = pileup[0].id().rsplitn(3, '_');
+        let _end = fields.next();
+        let start: usize = fields.next().and_then(|x| x.parse().ok()).unwrap_or(0);
+        let refr = fields.next().unwrap_or(pileup[0].id());
+        (refr.to_string(), start)
+    };
+    let mut rpos = start;
+    let mut calls = vec![];
+    for (column, &ref_base) in pileup[0].seq().iter().enumerate() {
+        let ref_base = ref_base.to_ascii_uppercase();
+        let alleles = count_alleles(&pileup[1..], column);
+        let depth: usize = alleles.iter().map(|x| x.1.total).sum();
+        let column_support = alleles
+            .iter()
+            .fold(Support::default(), |acc, (_, s)| Support {
+                total: acc.total + s.total,
+                forward: acc.forward + s.forward,
+                reverse: acc.reverse + s.reverse,
+            });
+        let alt = alleles.iter().find(|(base, support)| {
+            let freq = support.total as f64 / depth as f64;
+            *base != ref_base
+                && args.min_support <= support.total
+                && is_strand_balanced(support, &column_support, args)
+                && args.min_freq <= freq
+                && freq <= args.max_freq
+        });
+        if let Some(&alt) = alt {
+            let ref_support = alleles
+                .iter()
+                .find(|(base, _)| *base == ref_base)
+                .map(|x| x.1)
+                .unwrap_or_default();
+            calls.push(VariantCall {
+                column,
+                refr: refr.clone(),
+                position: rpos,
+                ref_base,
+                ref_support,
+                alt,
+                alleles,
+                depth,
+            });
         }
+        rpos += (ref_base != b'-') as usize;
+    }
+    calls
+}
+
+fn flush_calls(output: &std::path::Path, calls: &[VariantCall]) -> std::io::Result<()> {
+    use std::io::{BufWriter, Write};
+    let mut wtr = std::fs::File::create(output).map(BufWriter::new)?;
+    writeln!(
+        &mut wtr,
+        "Column\tRefr\tPosition\tRef\tRefCount\tAlt\tAltCount\tAltForward\tAltReverse\tDepth\tAltFreq\tAlleles"
+    )?;
+    for call in calls.iter() {
+        let (alt, alt_support) = call.alt;
+        let freq = alt_support.total as f64 / call.depth as f64;
+        let alleles: Vec<_> = call
+            .alleles
+            .iter()
+            .map(|(base, support)| format!("{}:{}", *base as char, support.total))
+            .collect();
+        writeln!(
+            &mut wtr,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{freq:.3}\t{}",
+            call.column,
+            call.refr,
+            call.position,
+            call.ref_base as char,
+            call.ref_support.total,
+            alt as char,
+            alt_support.total,
+            alt_support.forward,
+            alt_support.reverse,
+            call.depth,
+            alleles.join(",")
+        )?;
     }
-    summary
-        .iter()
-        .filter_map(|hm| {
-            let mut values: Vec<_> = hm.values().copied().collect();
-            values.sort();
-            values.pop();
-            values.pop()
-        })
-        .filter(|&count| min <= count)
-        .count()
+    Ok(())
 }
@@ -0,0 +1,390 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+/// Lift GFF3 features from the reference to the contigs by using alignments in MAF format.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Annotation in GFF3 format (*Reference*).
+    #[arg(short, long)]
+    gff: PathBuf,
+    /// Alignments in last MAF format. The first sequence of each block should be the reference.
+    #[arg(short, long)]
+    maf: PathBuf,
+    /// Output lifted features in GFF3 format.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Output the status of each feature in TSV format.
+    #[arg(short, long)]
+    report: PathBuf,
+    /// Filter out alignments below this size.
+    #[arg(long, default_value_t = 2000)]
+    min_aln_size: u64,
+    /// Features with lifted fraction below this value are regarded as unlifted.
+    #[arg(long, default_value_t = 0.5)]
+    min_coverage: f64,
+}
+
+use mito_check::gff::Feature;
+use std::collections::HashMap;
+use std::io::*;
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+    let features = mito_check::gff::parse_into_vec(&args.gff)?;
+    let maf = {
+        let mut maf = bio_utils::maf::parse_into_vec(&args.maf)?;
+        maf.retain(|record| {
+            let sequences = record.sequence();
+            let refr = &sequences[0];
+            let query = &sequences[1];
+            args.min_aln_size < refr.length().min(query.length())
+        });
+        maf
+    };
+    let blocks: Vec<_> = maf.iter().map(Block::new).collect();
+    let mut wtr = std::fs::File::create(&args.output).map(BufWriter::new)?;
+    let mut report = std::fs::File::create(&args.report).map(BufWriter::new)?;
+    writeln!(&mut wtr, "##gff-version 3")?;
+    writeln!(
+        &mut report,
+        "ID\tType\tRefr\tStart\tEnd\tStatus\tCoverage\tContigs"
+    )?;
+    // Pieces, coverage, and status of each feature.
+    let lifted: Vec<_> = features
+        .iter()
+        .map(|feature| {
+            let pieces = lift(feature, &blocks);
+            let (coverage, status) = lift_status(feature, &pieces, &args);
+            (pieces, coverage, status)
+        })
+        .collect();
+    // ID -> IDs of the lifted pieces. Split pieces get their own IDs.
+    let mut lifted_ids: HashMap<&str, Vec<String>> = HashMap::new();
+    for (feature, (pieces, _, status)) in std::iter::zip(features.iter(), lifted.iter()) {
+        if let (Some(id), false) = (feature.id(), *status == "Unlifted") {
+            let ids = match pieces.len() {
+                1 => vec![id.to_string()],
+                _ => (1..=pieces.len())
+                    .map(|i| format!("{id}_part{i}"))
+                    .collect(),
+            };
+            lifted_ids.insert(id, ids);
+        }
+    }
+    for (feature, (pieces, coverage, status)) in std::iter::zip(features.iter(), lifted) {
+        if status != "Unlifted" {
+            for (i, piece) in pieces.iter().enumerate() {
+                let mut attributes = lifted_attributes(feature, (i, pieces.len()), &lifted_ids);
+                attributes.push(("coverage".to_string(), format!("{coverage:.3}")));
+                if 1 < pieces.len() {
+                    attributes.push((
+                        "lift_part".to_string(),
+                        format!("{}/{}", i + 1, pieces.len()),
+                    ));
+                }
+                writeln!(&mut wtr, "{}", to_gff_line(feature, piece, &attributes))?;
+            }
+        }
+        let id = feature.id().or_else(|| feature.name()).unwrap_or(".");
+        let contigs: Vec<_> = pieces
+            .iter()
+            .map(|p| format!("{}:{}-{}", p.contig, p.start, p.end))
+            .collect();
+        let contigs = match contigs.is_empty() {
+            true => ".".to_string(),
+            false => contigs.join(","),
+        };
+        writeln!(
+            &mut report,
+            "{id}\t{}\t{}\t{}\t{}\t{status}\t{coverage:.3}\t{contigs}",
+            feature.feature_type, feature.seqid, feature.start, feature.end
+        )?;
+    }
+    Ok(())
+}
+
+fn lift_status(feature: &Feature, pieces: &[Piece], args: &Args) -> (f64, &'static str) {
+    let coverage = {
+        let lifted: usize = pieces.iter().map(|p| p.lifted).sum();
+        let length = feature
+            .end
+            .saturating_sub(feature.start.saturating_sub(1))
+            .max(1);
+        (lifted as f64 / length as f64).min(1f64)
+    };
+    let status = if coverage < args.min_coverage {
+        "Unlifted"
+    } else if 1 < pieces.len() {
+        "Split"
+    } else if coverage < 1f64 {
+        "Partial"
+    } else {
+        "Lifted"
+    };
+    (coverage, status)
+}
+
+// Attributes of the `i`-th piece out of `num` pieces. The ID is replaced by that of the piece,
+// and the parents are replaced by their lifted pieces. Parents not lifted are removed.
+fn lifted_attributes(
+    feature: &Feature,
+    (i, num): (usize, usize),
+    lifted_ids: &HashMap<&str, Vec<String>>,
+) -> Vec<(String, String)> {
+    feature
+        .attributes
+        .iter()
+        .filter_map(|(key, value)| match key.as_str() {
+            "ID" if 1 < num => Some((key.clone(), format!("{value}_part{}", i + 1))),
+            "Parent" => {
+                let parents: Vec<_> = value
+                    .split(',')
+                    .filter_map(|parent| lifted_ids.get(parent))
+                    .flatten()
+                    .map(|id| id.as_str())
+                    .collect();
+                (!parents.is_empty()).then(|| (key.clone(), parents.join(",")))
+            }
+            _ => Some((key.clone(), value.clone())),
+        })
+        .collect()
+}
+
+// An alignment block. Positions are on the forward strand of each sequence.
+#[derive(Debug, Clone)]
+struct Block {
+    refr: String,
+    contig: String,
+    // 0-based position of the first reference base.
+    rstart: usize,
+    rend: usize,
+    // Contig position (in the forward strand) for each reference base, None if deleted in the contig.
+    positions: Vec<Option<usize>>,
+    is_forward: bool,
+}
+
+// Name, start, source size, direction, and the aligned text of a row in MAF.
+type Row<'a> = (&'a str, usize, usize, bool, &'a [u8]);
+
+impl Block {
+    fn new(record: &bio_utils::maf::Record) -> Self {
+        let sequences = record.sequence();
+        fn to_row(seq: &bio_utils::maf::Seq) -> Row<'_> {
+            let (start, size) = (seq.start() as usize, seq.src_size() as usize);
+            (seq.name(), start, size, seq.is_forward(), seq.text())
+        }
+        let (refr, query) = (to_row(&sequences[0]), to_row(&sequences[1]));
+        Self::from_rows(refr, query)
+    }
+    // If the reference row is on the reverse strand, the columns are reversed and the query strand is flipped.
+    fn from_rows(refr: Row, query: Row) -> Self {
+        let (rname, rstart, rsize, r_is_forward, rtext) = refr;
+        let (qname, qstart, qsize, is_forward, qtext) = query;
+        let mut positions = Vec::with_capacity(rtext.len());
+        let mut qpos = 0;
+        for (&r, &q) in std::iter::zip(rtext, qtext) {
+            if r != b'-' {
+                let position = match (q == b'-', is_forward) {
+                    (true, _) => None,
+                    (false, true) => Some(qstart + qpos),
+                    (false, false) => Some(qsize - 1 - (qstart + qpos)),
+                };
+                positions.push(position);
+            }
+            qpos += (q != b'-') as usize;
+        }
+        let rstart = match r_is_forward {
+            true => rstart,
+            false => {
+                positions.reverse();
+                rsize - rstart - positions.len()
+            }
+        };
+        Self {
+            refr: rname.to_string(),
+            contig: qname.to_string(),
+            rstart,
+            rend: rstart + positions.len(),
+            positions,
+            is_forward: is_forward == r_is_forward,
+        }
+    }
+}
+
+// A lifted piece of a feature. 1-based, inclusive.
+#[derive(Debug, Clone)]
+struct Piece {
+    contig: String,
+    start: usize,
+    end: usize,
+    is_forward: bool,
+    // Number of lifted bases.
+    lifted: usize,
+    // The first and the last lifted reference bases. 0-based, half-open.
+    ref_start: usize,
+    ref_end: usize,
+}
+
+// Lift a feature. When the feature is lifted to several loci, the locus with the most lifted bases is retained.
+// Pieces from collinear blocks on the same contig are merged.
+fn lift(feature: &Feature, blocks: &[Block]) -> Vec<Piece> {
+    let (start, end) = (feature.start.saturating_sub(1), feature.end);
+    let mut pieces: Vec<Piece> = blocks
+        .iter()
+        .filter(|b| b.refr == feature.seqid && b.rstart < end && start < b.rend)
+        .filter_map(|b| {
+            let (s, e) = (start.max(b.rstart) - b.rstart, end.min(b.rend) - b.rstart);
+            // (Reference position, contig position)
+            let lifted: Vec<_> = (s..e)
+                .filter_map(|i| Some((b.rstart + i, b.positions[i]?)))
+                .collect();
+            let min = lifted.iter().map(|x| x.1).min()?;
+            let max = lifted.iter().map(|x| x.1).max()?;
+            Some(Piece {
+                contig: b.contig.clone(),
+                start: min + 1,
+                end: max + 1,
+                is_forward: b.is_forward,
+                lifted: lifted.len(),
+                ref_start: lifted.first()?.0,
+                ref_end: lifted.last()?.0 + 1,
+            })
+        })
+        .collect();
+    pieces.sort_by(|x, y| {
+        (&x.contig, x.is_forward, x.start).cmp(&(&y.contig, y.is_forward, y.start))
+    });
+    // Merge pieces on the same contig and the same strand if they are not too far apart.
+    let max_gap = end.saturating_sub(start);
+    let mut merged: Vec<Piece> = vec![];
+    for piece in pieces {
+        match merged.last_mut() {
+            Some(last)
+                if last.contig == piece.contig
+                    && last.is_forward == piece.is_forward
+                    && piece.start <= last.end + max_gap =>
+            {
+                last.end = last.end.max(piece.end);
+                last.lifted += piece.lifted;
+                last.ref_start = last.ref_start.min(piece.ref_start);
+                last.ref_end = last.ref_end.max(piece.ref_end);
+            }
+            _ => merged.push(piece),
+        }
+    }
+    let total = end.saturating_sub(start);
+    match merged.iter().max_by_key(|p| p.lifted) {
+        Some(best) if total <= best.lifted => vec![best.clone()],
+        Some(_) => {
+            // Partially lifted at several loci. Keep the largest pieces unless they exceed the feature.
+            merged.sort_by_key(|p| std::cmp::Reverse(p.lifted));
+            let mut pieces = vec![];
+            let mut lifted = 0;
+            for piece in merged {
+                if total < lifted + piece.lifted {
+                    break;
+                }
+                lifted += piece.lifted;
+                pieces.push(piece);
+            }
+            pieces
+        }
+        None => vec![],
+    }
+}
+
+fn to_gff_line(feature: &Feature, piece: &Piece, attributes: &[(String, String)]) -> String {
+    let score = match feature.score {
+        Some(score) => score.to_string(),
+        None => ".".to_string(),
+    };
+    let strand = match (feature.strand, piece.is_forward) {
+        (Some(x), y) if x == y => "+",
+        (Some(_), _) => "-",
+        (None, _) => ".",
+    };
+    let phase = match lifted_phase(feature, piece) {
+        Some(phase) => phase.to_string(),
+        None => ".".to_string(),
+    };
+    let attributes: Vec<_> = attributes.iter().map(|(k, v)| format!("{k}={v}")).collect();
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{score}\t{strand}\t{phase}\t{}",
+        piece.contig,
+        feature.source,
+        feature.feature_type,
+        piece.start,
+        piece.end,
+        attributes.join(";")
+    )
+}
+
+// The phase of a piece. The 5' end of the feature (in the reference) may be trimmed, shifting the reading frame.
+fn lifted_phase(feature: &Feature, piece: &Piece) -> Option<usize> {
+    let phase = feature.phase?;
+    let trimmed = match feature.strand {
+        Some(false) => feature.end.saturating_sub(piece.ref_end),
+        _ => piece
+            .ref_start
+            .saturating_sub(feature.start.saturating_sub(1)),
+    };
+    Some((phase + 3 - trimmed % 3) % 3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn reverse_reference_row() {
+        // The reference row is on the reverse strand, [2,6) of 10bp, i.e., [4,8) on the forward strand.
+        let refr = ("r", 2, 10, false, b"ACG-T".as_slice());
+        let query = ("c", 0, 5, true, b"AC-GT".as_slice());
+        let block = Block::from_rows(refr, query);
+        assert_eq!((block.rstart, block.rend), (4, 8));
+        assert_eq!(block.positions, vec![Some(3), None, Some(1), Some(0)]);
+        assert!(!block.is_forward);
+    }
+    #[test]
+    fn partial_feature() {
+        // [100,110) of the reference is aligned to [1000,1010) of the contig.
+        let block = Block {
+            refr: "r".to_string(),
+            contig: "c".to_string(),
+            rstart: 100,
+            rend: 110,
+            positions: (1000..1010).map(Some).collect(),
+            is_forward: true,
+        };
+        let line = "r\tsrc\tCDS\t96\t105\t.\t+\t0\tID=cds1";
+        let feature = Feature::new(line).unwrap();
+        let pieces = lift(&feature, std::slice::from_ref(&block));
+        assert_eq!(pieces.len(), 1);
+        let piece = &pieces[0];
+        assert_eq!((piece.start, piece.end, piece.lifted), (1001, 1005, 5));
+        assert_eq!((piece.ref_start, piece.ref_end), (100, 105));
+        // Codons start at 96, 99, 102, ... (1-based). The piece starts at 101.
+        assert_eq!(lifted_phase(&feature, piece), Some(1));
+        // On the reverse strand, the 5' end is 105, which is lifted.
+        let line = "r\tsrc\tCDS\t96\t105\t.\t-\t2\tID=cds2";
+        let feature = Feature::new(line).unwrap();
+        let pieces = lift(&feature, &[block]);
+        assert_eq!(lifted_phase(&feature, &pieces[0]), Some(2));
+    }
+    #[test]
+    fn split_ids_and_parents() {
+        let line = "r\tsrc\texon\t1\t10\t.\t+\t.\tID=exon1;Parent=mrna1,mrna2;Name=x";
+        let feature = Feature::new(line).unwrap();
+        let ids = vec!["mrna1_part1".to_string(), "mrna1_part2".to_string()];
+        let lifted_ids: HashMap<_, _> = [("mrna1", ids)].into_iter().collect();
+        let attributes = lifted_attributes(&feature, (1, 2), &lifted_ids);
+        let attribute = |key: &str| attributes.iter().find(|(k, _)| k == key).map(|x| &x.1);
+        assert_eq!(attribute("ID").unwrap(), "exon1_part2");
+        assert_eq!(attribute("Parent").unwrap(), "mrna1_part1,mrna1_part2");
+        assert_eq!(attribute("Name").unwrap(), "x");
+        // Parents not lifted are removed.
+        let attributes = lifted_attributes(&feature, (0, 1), &HashMap::new());
+        assert!(attributes.iter().all(|(k, _)| k != "Parent"));
+        assert!(attributes.contains(&("ID".to_string(), "exon1".to_string())));
+    }
+}
@@ -0,0 +1,278 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+/// Intersect break points and large indels with genes, and report the impact on each gene in TSV format.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Annotation in GFF3 format (*Reference*).
+    #[arg(short, long)]
+    gff: PathBuf,
+    /// Alignments in MAF or Last's TSV (`maf-convert tab`) format, one file per strain.
+    /// The strain name is the file name up to the first dot. In MAF, the first sequence of each block should be the reference.
+    #[arg(short, long, num_args = 1.., required = true)]
+    alignments: Vec<PathBuf>,
+    /// Outputs of `annotate_break_points`, one file per strain, named as the alignments. Used to locate junctions.
+    #[arg(short, long, num_args = 1..)]
+    break_points: Vec<PathBuf>,
+    /// Minimum size of the deletion.
+    #[arg(short, long, default_value_t = 500)]
+    min_sv_size: usize,
+    /// Genes covered less than this fraction are regarded as deleted.
+    #[arg(long, default_value_t = 0.5)]
+    min_coverage: f64,
+}
+
+use mito_check::gff::Feature;
+use mito_check::pairwise::Block;
+use std::collections::HashMap;
+use std::io::*;
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+    let features = mito_check::gff::parse_into_vec(&args.gff)?;
+    let genes: Vec<_> = features
+        .iter()
+        .filter(|f| f.feature_type == "gene")
+        .collect();
+    let exons = exons_of_genes(&features);
+    let mut impacts: Vec<HashMap<Impact, Vec<String>>> = vec![HashMap::new(); genes.len()];
+    let mut disrupted_exons: Vec<Vec<String>> = vec![vec![]; genes.len()];
+    let mut break_points: HashMap<_, Vec<_>> = HashMap::new();
+    for path in args.break_points.iter() {
+        break_points
+            .entry(mito_check::strain_name(path))
+            .or_default()
+            .extend(parse_blocks(path)?);
+    }
+    for path in args.alignments.iter() {
+        let strain = mito_check::strain_name(path);
+        let blocks = parse_alignments(path)?;
+        let deletions = find_deletions(&blocks, args.min_sv_size);
+        let junctions = break_points.get(&strain).map_or(&[][..], |x| x.as_slice());
+        for (i, gene) in genes.iter().enumerate() {
+            let gene_id = gene.id().unwrap_or("");
+            let empty = vec![];
+            let gene_exons = exons.get(gene_id).unwrap_or(&empty);
+            let aln = (blocks.as_slice(), junctions, deletions.as_slice());
+            let (impact, exons) = classify(gene, gene_exons, aln, &args);
+            impacts[i].entry(impact).or_default().push(strain.clone());
+            disrupted_exons[i].extend(exons.into_iter().map(|e| format!("{strain}:{e}")));
+        }
+    }
+    println!("Gene\tRefr\tStart\tEnd\tStrand\tImpact\tNumStrains\tStrains\tDisruptedExons");
+    for ((gene, impacts), exons) in genes.iter().zip(impacts).zip(disrupted_exons) {
+        let name = gene.name().unwrap_or(".");
+        let strand = match gene.strand {
+            Some(true) => "+",
+            Some(false) => "-",
+            None => ".",
+        };
+        let mut impacts: Vec<_> = impacts.into_iter().collect();
+        impacts.sort_by_key(|x| x.0);
+        for (impact, strains) in impacts {
+            let exons: Vec<_> = exons
+                .iter()
+                .filter(|e| strains.iter().any(|s| e.starts_with(&format!("{s}:"))))
+                .map(|e| e.as_str())
+                .collect();
+            let exons = match exons.is_empty() || impact != Impact::Disrupted {
+                true => ".".to_string(),
+                false => exons.join(","),
+            };
+            println!(
+                "{name}\t{}\t{}\t{}\t{strand}\t{impact}\t{}\t{}\t{exons}",
+                gene.seqid,
+                gene.start,
+                gene.end,
+                strains.len(),
+                strains.join(",")
+            );
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Impact {
+    Intact,
+    Disrupted,
+    Duplicated,
+    Deleted,
+}
+
+impl std::fmt::Display for Impact {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let impact = match self {
+            Impact::Intact => "Intact",
+            Impact::Disrupted => "Disrupted",
+            Impact::Duplicated => "Duplicated",
+            Impact::Deleted => "Deleted",
+        };
+        write!(f, "{impact}")
+    }
+}
+
+// Gene ID -> exons. Exons are assigned to the gene by following their parents.
+fn exons_of_genes(features: &[Feature]) -> HashMap<String, Vec<&Feature>> {
+    let parents: HashMap<_, _> = features
+        .iter()
+        .filter_map(|f| Some((f.id()?, f.parent()?)))
+        .collect();
+    let mut exons: HashMap<_, Vec<_>> = HashMap::new();
+    for exon in features.iter().filter(|f| f.feature_type == "exon") {
+        let mut ancestor = match exon.parent() {
+            Some(parent) => parent,
+            None => continue,
+        };
+        while let Some(parent) = parents.get(ancestor) {
+            ancestor = parent;
+        }
+        exons.entry(ancestor.to_string()).or_default().push(exon);
+    }
+    exons
+}
+
+// An alignment, or a line of `annotate_break_points`. Contig, start, end, reference, start, end.
+// 0-based, half-open, and on the forward strand of each sequence.
+type AlnBlock = (String, usize, usize, String, usize, usize);
+fn parse_alignments(path: &std::path::Path) -> std::io::Result<Vec<AlnBlock>> {
+    let lines: Vec<_> = std::fs::File::open(path)
+        .map(BufReader::new)?
+        .lines()
+        .map_while(Result::ok)
+        .collect();
+    let blocks: Vec<_> = if lines
+        .iter()
+        .any(|l| l.starts_with("##maf") || l.starts_with("a "))
+    {
+        bio_utils::maf::parse_into_vec(path)?
+            .iter()
+            .filter_map(Block::from_maf)
+            .collect()
+    } else {
+        lines
+            .iter()
+            .filter(|l| !l.starts_with('#'))
+            .filter_map(|l| Block::from_tab(l))
+            .collect()
+    };
+    let blocks = blocks
+        .into_iter()
+        .map(|b| (b.query, b.qstart, b.qend, b.refr, b.rstart, b.rend))
+        .collect();
+    Ok(blocks)
+}
+
+fn parse_blocks(path: &std::path::Path) -> std::io::Result<Vec<AlnBlock>> {
+    let blocks = std::fs::File::open(path)
+        .map(BufReader::new)?
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| {
+            let line: Vec<_> = line.split('\t').collect();
+            let c_start: usize = line.get(1)?.parse().ok()?;
+            let c_end: usize = line.get(2)?.parse().ok()?;
+            let r_start: usize = line.get(4)?.parse().ok()?;
+            let r_end: usize = line.get(5)?.parse().ok()?;
+            let (contig, refr) = (line[0].to_string(), line[3].to_string());
+            Some((contig, c_start, c_end, refr, r_start, r_end))
+        })
+        .collect();
+    Ok(blocks)
+}
+
+// Deleted reference intervals, i.e., adjacent blocks in a contig are adjacent in the contig but far apart in the reference.
+fn find_deletions(blocks: &[AlnBlock], min_sv_size: usize) -> Vec<(String, usize, usize)> {
+    let mut contigs: HashMap<_, Vec<_>> = HashMap::new();
+    for block in blocks.iter() {
+        contigs.entry(&block.0).or_default().push(block);
+    }
+    let mut deletions = vec![];
+    for blocks in contigs.values_mut() {
+        blocks.sort_by_key(|b| (b.1, b.2));
+        for pair in blocks.windows(2) {
+            let (prev, next) = (pair[0], pair[1]);
+            let contig_gap = next.1.saturating_sub(prev.2);
+            if prev.3 != next.3 || min_sv_size <= contig_gap {
+                continue;
+            }
+            let deleted = if prev.5 <= next.4 {
+                (prev.5, next.4)
+            } else if next.5 <= prev.4 {
+                (next.5, prev.4)
+            } else {
+                continue;
+            };
+            if min_sv_size <= deleted.1 - deleted.0 + contig_gap {
+                deletions.push((prev.3.clone(), deleted.0, deleted.1));
+            }
+        }
+    }
+    deletions
+}
+
+// Alignments, break points, and deletions of a strain.
+type StrainAlignments<'a> = (&'a [AlnBlock], &'a [AlnBlock], &'a [(String, usize, usize)]);
+// Classify the impact on the gene, and return disrupted exons.
+// The coverage and the copy number come from the alignments, and the junctions from the break points.
+fn classify(
+    gene: &Feature,
+    exons: &[&Feature],
+    (blocks, break_points, deletions): StrainAlignments,
+    args: &Args,
+) -> (Impact, Vec<String>) {
+    // 0-based, half-open.
+    let (start, end) = (gene.start.saturating_sub(1), gene.end);
+    let blocks: Vec<_> = blocks.iter().filter(|b| b.3 == gene.seqid).collect();
+    let covered = {
+        let mut intervals: Vec<_> = blocks
+            .iter()
+            .map(|b| (b.4.max(start), b.5.min(end)))
+            .filter(|(s, e)| s < e)
+            .collect();
+        intervals.sort_unstable();
+        let (mut covered, mut reach) = (0, start);
+        for (s, e) in intervals {
+            covered += e.saturating_sub(s.max(reach));
+            reach = reach.max(e);
+        }
+        covered
+    };
+    if (covered as f64) < args.min_coverage * (end - start) as f64 {
+        return (Impact::Deleted, vec![]);
+    }
+    // Junctions strictly inside the gene, and deletions overlapping the gene.
+    let mut junctions: Vec<_> = break_points
+        .iter()
+        .filter(|b| b.3 == gene.seqid)
+        .flat_map(|b| [b.4, b.5])
+        .filter(|&pos| start < pos && pos < end)
+        .map(|pos| (pos, pos + 1))
+        .collect();
+    junctions.extend(
+        deletions
+            .iter()
+            .filter(|d| d.0 == gene.seqid && d.1 < end && start < d.2)
+            .map(|d| (d.1, d.2)),
+    );
+    if !junctions.is_empty() {
+        let exons = exons
+            .iter()
+            .filter(|exon| {
+                let (s, e) = (exon.start.saturating_sub(1), exon.end);
+                junctions.iter().any(|&(js, je)| js < e && s < je)
+            })
+            .map(|exon| {
+                let name = exon.id().unwrap_or(".");
+                format!("{name}({}-{})", exon.start, exon.end)
+            })
+            .collect();
+        return (Impact::Disrupted, exons);
+    }
+    let copies = blocks.iter().filter(|b| b.4 <= start && end <= b.5).count();
+    match copies {
+        0 | 1 => (Impact::Intact, vec![]),
+        _ => (Impact::Duplicated, vec![]),
+    }
+}
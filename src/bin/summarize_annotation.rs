@@ -17,11 +17,37 @@ struct Args {
     #[arg(short, long)]
     maf: PathBuf,
     /// Output variant information into.
+    /// Coverages and assembly metrics are written to the same path with `.coverage.tsv` and `.qc.tsv` extensions.
     #[arg(short, long)]
     output: PathBuf,
     /// Filter out alignments below this size.
     #[arg(short, long, default_value_t = 2000)]
     min_aln_size: u64,
+    /// Reference genome in FASTA format. Required to predict coding effects.
+    /// Reference sequences without any alignment are reported as uncovered.
+    #[arg(short, long)]
+    reference: Option<PathBuf>,
+    /// Output coding effects of the variants into.
+    #[arg(long, requires = "reference")]
+    effects: Option<PathBuf>,
+    /// NCBI translation table. Plant mitochondria use the standard code (1).
+    #[arg(long, default_value_t = 1)]
+    translation_table: usize,
+    /// Known RNA editing sites in TSV (Refr, 1-based position).
+    #[arg(long)]
+    editing_sites: Option<PathBuf>,
+    /// Expected genome size for NG50. If not given, the total length of the reference is used.
+    #[arg(long)]
+    expected_genome_size: Option<u64>,
+    /// Minimum overlap between the head and the tail of a contig to be regarded as circular.
+    #[arg(long, default_value_t = 100)]
+    min_circular_overlap: usize,
+    /// Maximum overlap between the head and the tail of a contig to search.
+    #[arg(long, default_value_t = 10_000)]
+    max_circular_overlap: usize,
+    /// Gaps between adjacent alignments larger than this are relocations.
+    #[arg(long, default_value_t = 1000)]
+    relocation_size: u64,
 }
 
 use std::io::prelude::*;
@@ -29,6 +55,10 @@ use std::io::BufReader;
 fn main() -> std::io::Result<()> {
     let args = Args::parse();
     let contigs = bio_utils::fasta::parse_into_vec(&args.contigs)?;
+    let reference = match &args.reference {
+        Some(path) => Some(bio_utils::fasta::parse_into_vec(path)?),
+        None => None,
+    };
     let maf = {
         let mut maf = bio_utils::maf::parse_into_vec(&args.maf)?;
         maf.retain(|record| {
@@ -72,7 +102,31 @@ fn main() -> std::io::Result<()> {
         }
     }
     let variations = variants.len();
-    let (cov_refr, cov_contigs) = get_coverage(&maf);
+    let (refr_coverages, contig_coverages) = get_coverage(&maf, &contigs, reference.as_deref());
+    let (cov_refr, cov_contigs) = (
+        coverage_rate(&refr_coverages),
+        coverage_rate(&contig_coverages),
+    );
+    {
+        let mut wtr = std::fs::File::create(args.output.with_extension("coverage.tsv"))
+            .map(BufWriter::new)?;
+        flush_coverages(&mut wtr, &refr_coverages, &contig_coverages)?;
+    }
+    {
+        let refr_size = match (args.expected_genome_size, reference.as_ref()) {
+            (Some(size), _) => size,
+            (None, Some(reference)) => reference.iter().map(|r| r.seq().len() as u64).sum(),
+            (None, None) => refr_coverages.iter().map(|c| c.length).sum(),
+        };
+        let mut metrics = assembly_metrics(&contigs, refr_size, &args);
+        metrics.extend(misassembly_metrics(&maf, args.relocation_size));
+        let mut wtr =
+            std::fs::File::create(args.output.with_extension("qc.tsv")).map(BufWriter::new)?;
+        writeln!(&mut wtr, "Metric\tValue")?;
+        for (metric, value) in metrics {
+            writeln!(&mut wtr, "{metric}\t{value}")?;
+        }
+    }
     println!("{genome_size}\t{num_contigs}\t{variations}\t{cov_refr}\t{cov_contigs}");
     // GFF
     let gff: Vec<_> = std::fs::File::open(&args.gff)
@@ -109,38 +163,527 @@ fn main() -> std::io::Result<()> {
             writeln!(&mut wtr, "{rname}\t{position}\t{var_type}\tNonExon")?;
         }
     }
+    if let (Some(effects), Some(reference)) = (&args.effects, reference.as_ref()) {
+        let editing_sites = match &args.editing_sites {
+            Some(path) => parse_editing_sites(path)?,
+            None => HashSet::new(),
+        };
+        let code = match GeneticCode::new(args.translation_table) {
+            Some(code) => code,
+            None => {
+                let table = args.translation_table;
+                let msg = format!("Unsupported translation table:{table}");
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+            }
+        };
+        let features = mito_check::gff::parse_into_vec(&args.gff)?;
+        let events = enumerate_events(&maf);
+        let mut wtr = std::fs::File::create(effects).map(BufWriter::new)?;
+        flush_effects(
+            &mut wtr,
+            reference,
+            &features,
+            &events,
+            &code,
+            &editing_sites,
+        )?;
+    }
     Ok(())
 }
 
-use std::collections::HashMap;
+// Reference name, 0-based position, reference allele, and alternative allele.
+// Insertions have empty reference alleles, and deletions have empty alternative alleles.
+type Event = (String, usize, Vec<u8>, Vec<u8>);
+fn enumerate_events(maf: &[bio_utils::maf::Record]) -> Vec<Event> {
+    let mut events = vec![];
+    for record in maf.iter() {
+        let sequences = record.sequence();
+        let refr = &sequences[0];
+        let query = &sequences[1];
+        let start = refr.start() as usize;
+        // Offset in the alignment, reference allele, alternative allele.
+        let mut in_aln: Vec<(usize, Vec<u8>, Vec<u8>)> = vec![];
+        let mut rpos = 0;
+        let mut prev = b'M';
+        for (&r, &q) in std::iter::zip(refr.text(), query.text()) {
+            let (r, q) = (r.to_ascii_uppercase(), q.to_ascii_uppercase());
+            let op = if r == b'-' {
+                b'I'
+            } else if q == b'-' {
+                b'D'
+            } else if r != q {
+                b'X'
+            } else {
+                b'M'
+            };
+            match (op, in_aln.last_mut()) {
+                (b'I', Some(last)) if prev == b'I' => last.2.push(q),
+                (b'I', _) => in_aln.push((rpos, vec![], vec![q])),
+                (b'D', Some(last)) if prev == b'D' => last.1.push(r),
+                (b'D', _) => in_aln.push((rpos, vec![r], vec![])),
+                (b'X', _) => in_aln.push((rpos, vec![r], vec![q])),
+                _ => {}
+            }
+            prev = op;
+            rpos += (r != b'-') as usize;
+        }
+        let refname = refr.name().to_string();
+        if refr.is_forward() {
+            events.extend(
+                in_aln
+                    .into_iter()
+                    .map(|(offset, r, q)| (refname.clone(), start + offset, r, q)),
+            );
+        } else {
+            let size = refr.src_size() as usize;
+            events.extend(in_aln.into_iter().map(|(offset, r, q)| {
+                let position = match r.is_empty() {
+                    true => size - start - offset,
+                    false => size - start - offset - r.len(),
+                };
+                (
+                    refname.clone(),
+                    position,
+                    bio_utils::revcmp(&r),
+                    bio_utils::revcmp(&q),
+                )
+            }));
+        }
+    }
+    events
+}
+
+fn parse_editing_sites(path: &std::path::Path) -> std::io::Result<HashSet<(String, usize)>> {
+    let sites = std::fs::File::open(path)
+        .map(BufReader::new)?
+        .lines()
+        .filter_map(|l| l.ok())
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let refname = fields.next()?.to_string();
+            let position: usize = fields.next()?.parse().ok()?;
+            Some((refname, position.checked_sub(1)?))
+        })
+        .collect();
+    Ok(sites)
+}
 
-fn get_coverage(mafs: &[bio_utils::maf::Record]) -> (f64, f64) {
-    let (refr_sizes, contig_sizes): (HashMap<_, _>, HashMap<_, _>) = mafs
+use mito_check::coding::{CodingSequence, GeneticCode};
+use std::collections::HashSet;
+fn flush_effects<W: std::io::Write>(
+    wtr: &mut W,
+    reference: &[bio_utils::fasta::Record],
+    features: &[mito_check::gff::Feature],
+    events: &[Event],
+    code: &GeneticCode,
+    editing_sites: &HashSet<(String, usize)>,
+) -> std::io::Result<()> {
+    let genomes: HashMap<_, _> = reference
         .iter()
-        .map(|record| {
-            let sequences = record.sequence();
-            let refr = &sequences[0];
-            let contig = &sequences[1];
-            let refr = (refr.name().to_string(), refr.src_size());
-            let contig = (contig.name().to_string(), contig.src_size());
-            (refr, contig)
+        .map(|r| (r.id().to_string(), r.seq()))
+        .collect();
+    let cdss: Vec<(CodingSequence, Vec<u8>)> = mito_check::coding::coding_sequences(features)
+        .into_iter()
+        .filter_map(|cds| {
+            let mut seq = cds.sequence(&genomes)?;
+            cds.apply_editing(&mut seq, editing_sites);
+            Some((cds, seq))
         })
-        .unzip();
-    let mut refr_covered: HashMap<_, _> = refr_sizes.keys().cloned().map(|x| (x, 0)).collect();
-    let mut contig_covered: HashMap<_, _> = contig_sizes.keys().cloned().map(|x| (x, 0)).collect();
+        .collect();
+    let to_str = |xs: &[u8]| match xs.is_empty() {
+        true => ".".to_string(),
+        false => String::from_utf8_lossy(xs).to_string(),
+    };
+    writeln!(
+        wtr,
+        "Refr\tPosition\tRef\tAlt\tTranscript\tGene\tCdsPos\tRefCodon\tAltCodon\tRefAA\tAltAA\tEffect\tEditing"
+    )?;
+    for (rname, position, refr, alt) in events.iter() {
+        let edited = (*position..*position + refr.len().max(1))
+            .any(|pos| editing_sites.contains(&(rname.clone(), pos)));
+        let edited = if edited { "Edited" } else { "." };
+        for (cds, seq) in cdss.iter() {
+            let pred = match cds.predict(seq, code, rname, *position, (refr, alt)) {
+                Some(pred) => pred,
+                None => continue,
+            };
+            let (ref_aa, alt_aa) = (
+                pred.ref_aa.map(|x| x as char),
+                pred.alt_aa.map(|x| x as char),
+            );
+            writeln!(
+                wtr,
+                "{rname}\t{position}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{edited}",
+                to_str(refr),
+                to_str(alt),
+                cds.id,
+                cds.gene,
+                pred.cds_position,
+                to_str(&pred.ref_codon),
+                to_str(&pred.alt_codon),
+                ref_aa.unwrap_or('.'),
+                alt_aa.unwrap_or('.'),
+                pred.effect,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+use mito_check::pairwise::{to_forward, Block};
+use std::collections::HashMap;
+
+/// Covered bases of a sequence, where the coverage is the union of the aligned intervals.
+#[derive(Debug, Clone)]
+struct Coverage {
+    name: String,
+    length: u64,
+    covered: u64,
+    duplicated: u64,
+    // 0-based, half-open intervals.
+    uncovered_intervals: Vec<(u64, u64)>,
+    duplicated_intervals: Vec<(u64, u64)>,
+}
+
+impl Coverage {
+    fn new(name: &str, length: u64, intervals: &[(u64, u64)]) -> Self {
+        let mut boundaries: Vec<(u64, i64)> = intervals
+            .iter()
+            .flat_map(|&(start, end)| [(start, 1), (end, -1)])
+            .collect();
+        boundaries.push((length, 0));
+        boundaries.sort_unstable();
+        let (mut uncovered_intervals, mut duplicated_intervals) = (vec![], vec![]);
+        let (mut depth, mut prev) = (0, 0);
+        for (pos, diff) in boundaries {
+            if prev < pos {
+                let slot: Option<&mut Vec<(u64, u64)>> = match depth {
+                    0 => Some(&mut uncovered_intervals),
+                    1 => None,
+                    _ => Some(&mut duplicated_intervals),
+                };
+                if let Some(slot) = slot {
+                    match slot.last_mut() {
+                        Some(last) if last.1 == prev => last.1 = pos,
+                        _ => slot.push((prev, pos)),
+                    }
+                }
+            }
+            depth += diff;
+            prev = pos;
+        }
+        let uncovered: u64 = uncovered_intervals.iter().map(|(s, e)| e - s).sum();
+        let duplicated: u64 = duplicated_intervals.iter().map(|(s, e)| e - s).sum();
+        Self {
+            name: name.to_string(),
+            length,
+            covered: length - uncovered,
+            duplicated,
+            uncovered_intervals,
+            duplicated_intervals,
+        }
+    }
+}
+
+// Sequences without any alignment are included as uncovered, which are all the contigs and the reference sequences if given.
+fn get_coverage(
+    mafs: &[bio_utils::maf::Record],
+    contigs: &[bio_utils::fasta::Record],
+    reference: Option<&[bio_utils::fasta::Record]>,
+) -> (Vec<Coverage>, Vec<Coverage>) {
+    // Name -> (length, aligned intervals on the forward strand)
+    let mut refr_intervals: HashMap<_, (u64, Vec<_>)> = reference
+        .unwrap_or(&[])
+        .iter()
+        .map(|r| (r.id().to_string(), (r.seq().len() as u64, vec![])))
+        .collect();
+    let mut contig_intervals: HashMap<_, (u64, Vec<_>)> = contigs
+        .iter()
+        .map(|c| (c.id().to_string(), (c.seq().len() as u64, vec![])))
+        .collect();
     for record in mafs {
         let sequences = record.sequence();
-        let refr = &sequences[0];
-        let contig = &sequences[1];
-        *refr_covered.get_mut(refr.name()).unwrap() += refr.length();
-        *contig_covered.get_mut(contig.name()).unwrap() += contig.length();
-    }
-    let refr_cov: u64 = refr_covered.values().sum();
-    let refr_len: u64 = refr_sizes.values().sum();
-    let contig_cov: u64 = contig_covered.values().sum();
-    let contig_len: u64 = contig_sizes.values().sum();
-    (
-        refr_cov as f64 / refr_len as f64,
-        contig_cov as f64 / contig_len as f64,
-    )
+        for (seq, intervals) in [
+            (&sequences[0], &mut refr_intervals),
+            (&sequences[1], &mut contig_intervals),
+        ] {
+            let size = seq.src_size();
+            let (start, len) = (seq.start() as usize, seq.length() as usize);
+            let (start, end) = to_forward(start, len, size as usize, seq.is_forward());
+            let slot = intervals.entry(seq.name().to_string()).or_default();
+            slot.0 = size;
+            slot.1.push((start as u64, end as u64));
+        }
+    }
+    let to_coverages = |intervals: HashMap<String, (u64, Vec<_>)>| {
+        let mut coverages: Vec<_> = intervals
+            .iter()
+            .map(|(name, (length, intervals))| Coverage::new(name, *length, intervals))
+            .collect();
+        coverages.sort_by(|x, y| x.name.cmp(&y.name));
+        coverages
+    };
+    (to_coverages(refr_intervals), to_coverages(contig_intervals))
+}
+
+fn coverage_rate(coverages: &[Coverage]) -> f64 {
+    let covered: u64 = coverages.iter().map(|c| c.covered).sum();
+    let length: u64 = coverages.iter().map(|c| c.length).sum();
+    covered as f64 / length as f64
+}
+
+fn flush_coverages<W: std::io::Write>(
+    wtr: &mut W,
+    refr_coverages: &[Coverage],
+    contig_coverages: &[Coverage],
+) -> std::io::Result<()> {
+    let to_str = |intervals: &[(u64, u64)]| match intervals.is_empty() {
+        true => ".".to_string(),
+        false => {
+            let intervals: Vec<_> = intervals.iter().map(|(s, e)| format!("{s}-{e}")).collect();
+            intervals.join(",")
+        }
+    };
+    writeln!(
+        wtr,
+        "Role\tName\tLength\tCovered\tDuplicated\tCoverage\tUncoveredIntervals\tDuplicatedIntervals"
+    )?;
+    let refrs = refr_coverages.iter().map(|c| ("Refr", c));
+    let contigs = contig_coverages.iter().map(|c| ("Contig", c));
+    for (role, cov) in refrs.chain(contigs) {
+        let rate = cov.covered as f64 / cov.length as f64;
+        writeln!(
+            wtr,
+            "{role}\t{}\t{}\t{}\t{}\t{rate}\t{}\t{}",
+            cov.name,
+            cov.length,
+            cov.covered,
+            cov.duplicated,
+            to_str(&cov.uncovered_intervals),
+            to_str(&cov.duplicated_intervals),
+        )?;
+    }
+    Ok(())
+}
+
+fn assembly_metrics(
+    contigs: &[bio_utils::fasta::Record],
+    expected_size: u64,
+    args: &Args,
+) -> Vec<(&'static str, String)> {
+    let mut lengths: Vec<_> = contigs.iter().map(|c| c.seq().len() as u64).collect();
+    lengths.sort_unstable_by(|x, y| y.cmp(x));
+    let total: u64 = lengths.iter().sum();
+    let (n50, l50) = nx50(&lengths, total);
+    let (ng50, lg50) = nx50(&lengths, expected_size);
+    let (gc, n) = contigs
+        .iter()
+        .flat_map(|c| c.seq().iter())
+        .fold((0, 0), |(gc, n), base| match base.to_ascii_uppercase() {
+            b'G' | b'C' => (gc + 1, n),
+            b'N' => (gc, n + 1),
+            _ => (gc, n),
+        });
+    let acgt = total - n;
+    let circulars: Vec<_> = contigs
+        .iter()
+        .filter_map(|c| {
+            let overlap = terminal_overlap(
+                c.seq(),
+                args.min_circular_overlap,
+                args.max_circular_overlap,
+            )?;
+            Some(format!("{}:{overlap}", c.id()))
+        })
+        .collect();
+    let circulars = match circulars.is_empty() {
+        true => ".".to_string(),
+        false => circulars.join(","),
+    };
+    vec![
+        ("NumContigs", lengths.len().to_string()),
+        ("TotalLength", total.to_string()),
+        ("LargestContig", lengths.first().unwrap_or(&0).to_string()),
+        ("N50", n50.to_string()),
+        ("L50", l50.to_string()),
+        ("NG50", ng50.to_string()),
+        ("LG50", lg50.to_string()),
+        ("GC", (gc as f64 / acgt as f64).to_string()),
+        ("NContent", (n as f64 / total as f64).to_string()),
+        ("CircularContigs", circulars),
+    ]
+}
+
+// Return (N50, L50) with respect to `size`. If the contigs do not reach the half of the size, return (0,0).
+// `lengths` should be sorted in descending order.
+fn nx50(lengths: &[u64], size: u64) -> (u64, usize) {
+    let mut acc = 0;
+    for (i, &len) in lengths.iter().enumerate() {
+        acc += len;
+        if size <= 2 * acc {
+            return (len, i + 1);
+        }
+    }
+    (0, 0)
+}
+
+// Return the longest exact overlap between the head and the tail of the sequence.
+fn terminal_overlap(seq: &[u8], min: usize, max: usize) -> Option<usize> {
+    let max = max.min(seq.len().saturating_sub(1));
+    (min..=max)
+        .rev()
+        .find(|&len| seq[..len].eq_ignore_ascii_case(&seq[seq.len() - len..]))
+}
+
+// Contig start, contig end, reference name, ref start, ref end, reference size, and direction.
+type ContigBlock<'a> = (u64, u64, &'a str, u64, u64, u64, bool);
+
+fn misassembly_metrics(
+    mafs: &[bio_utils::maf::Record],
+    relocation_size: u64,
+) -> Vec<(&'static str, String)> {
+    // Contig -> Blocks.
+    let alignments: Vec<_> = mafs.iter().filter_map(Block::from_maf).collect();
+    let mut blocks: HashMap<_, Vec<ContigBlock>> = HashMap::new();
+    for aln in alignments.iter() {
+        let (qstart, qend) = (aln.qstart as u64, aln.qend as u64);
+        let (rstart, rend) = (aln.rstart as u64, aln.rend as u64);
+        let block = (
+            qstart,
+            qend,
+            aln.refr.as_str(),
+            rstart,
+            rend,
+            aln.rlen as u64,
+            aln.is_forward,
+        );
+        blocks.entry(aln.query.as_str()).or_default().push(block);
+    }
+    let (relocations, inversions, translocations) =
+        count_misassemblies(blocks.into_values(), relocation_size);
+    vec![
+        ("Relocations", relocations.to_string()),
+        ("Inversions", inversions.to_string()),
+        ("Translocations", translocations.to_string()),
+        (
+            "Misassemblies",
+            (relocations + inversions + translocations).to_string(),
+        ),
+    ]
+}
+
+// Return the number of relocations, inversions, and translocations between adjacent blocks of each contig.
+fn count_misassemblies<'a, I: Iterator<Item = Vec<ContigBlock<'a>>>>(
+    contigs: I,
+    relocation_size: u64,
+) -> (usize, usize, usize) {
+    let (mut relocations, mut inversions, mut translocations) = (0, 0, 0);
+    for mut blocks in contigs {
+        blocks.sort_by_key(|b| (b.0, b.1));
+        for pair in blocks.windows(2) {
+            let (prev, next) = (&pair[0], &pair[1]);
+            if prev.2 != next.2 {
+                translocations += 1;
+            } else if prev.6 != next.6 {
+                inversions += 1;
+            } else {
+                let contig_gap = next.0 as i64 - prev.1 as i64;
+                let (refr_gap, wraps) = match prev.6 {
+                    true => (
+                        next.3 as i64 - prev.4 as i64,
+                        prev.5 <= prev.4 + relocation_size && next.3 <= relocation_size,
+                    ),
+                    false => (
+                        prev.3 as i64 - next.4 as i64,
+                        prev.3 <= relocation_size && prev.5 <= next.4 + relocation_size,
+                    ),
+                };
+                // Alignments across the origin of a circular reference are not misassemblies.
+                if relocation_size < (refr_gap - contig_gap).unsigned_abs() && !wraps {
+                    relocations += 1;
+                }
+            }
+        }
+    }
+    (relocations, inversions, translocations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn coverage_union() {
+        // Overlapping [10,30) and [20,40), adjacent [40,50), and [70,80) contained in [60,90).
+        let intervals = [(10, 30), (20, 40), (40, 50), (60, 90), (70, 80)];
+        let coverage = Coverage::new("refr", 100, &intervals);
+        assert_eq!(coverage.covered, 70);
+        assert_eq!(coverage.duplicated, 20);
+        assert_eq!(
+            coverage.uncovered_intervals,
+            vec![(0, 10), (50, 60), (90, 100)]
+        );
+        assert_eq!(coverage.duplicated_intervals, vec![(20, 30), (70, 80)]);
+    }
+    #[test]
+    fn ng50() {
+        let lengths = [50, 30, 20];
+        assert_eq!(nx50(&lengths, 100), (50, 1));
+        // NG50 with a genome larger than the assembly.
+        assert_eq!(nx50(&lengths, 160), (30, 2));
+        assert_eq!(nx50(&lengths, 200), (20, 3));
+        assert_eq!(nx50(&lengths, 300), (0, 0));
+    }
+    #[test]
+    fn circular_overlap() {
+        // The head `ACGTA` appears again at the tail, i.e., a circular junction.
+        let seq = b"ACGTAGGCCTTacgta";
+        assert_eq!(terminal_overlap(seq, 2, 10), Some(5));
+        assert_eq!(terminal_overlap(seq, 6, 10), None);
+        assert_eq!(terminal_overlap(seq, 2, 3), None);
+        assert_eq!(terminal_overlap(b"AAAA", 1, 10), Some(3));
+    }
+    #[test]
+    fn misassemblies() {
+        let relocation = vec![
+            (0, 100, "r", 0, 100, 1000, true),
+            (100, 200, "r", 500, 600, 1000, true),
+        ];
+        let inversion = vec![
+            (0, 100, "r", 0, 100, 1000, true),
+            (100, 200, "r", 100, 200, 1000, false),
+        ];
+        let translocation = vec![
+            (0, 100, "r", 0, 100, 1000, true),
+            (100, 200, "s", 100, 200, 1000, true),
+        ];
+        // Across the origin of the circular reference.
+        let wrap = vec![
+            (100, 200, "r", 0, 100, 1000, true),
+            (0, 100, "r", 900, 1000, 1000, true),
+        ];
+        let reverse_wrap = vec![
+            (0, 100, "r", 0, 100, 1000, false),
+            (100, 200, "r", 900, 1000, 1000, false),
+        ];
+        let count =
+            |blocks: Vec<ContigBlock<'static>>| count_misassemblies([blocks].into_iter(), 50);
+        assert_eq!(count(relocation), (1, 0, 0));
+        assert_eq!(count(inversion), (0, 1, 0));
+        assert_eq!(count(translocation), (0, 0, 1));
+        assert_eq!(count(wrap), (0, 0, 0));
+        assert_eq!(count(reverse_wrap), (0, 0, 0));
+        // Small gaps are not relocations.
+        let indel = vec![
+            (0, 100, "r", 0, 100, 1000, true),
+            (100, 200, "r", 130, 230, 1000, true),
+        ];
+        assert_eq!(count(indel), (0, 0, 0));
+    }
+    #[test]
+    fn coverage_without_alignments() {
+        let coverage = Coverage::new("refr", 100, &[]);
+        assert_eq!(coverage.covered, 0);
+        assert_eq!(coverage.uncovered_intervals, vec![(0, 100)]);
+        assert!(coverage.duplicated_intervals.is_empty());
+    }
 }
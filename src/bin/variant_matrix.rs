@@ -0,0 +1,244 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+/// Merge variants of many strains into a site x strain matrix, and compute distances between strains.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Variants, one file per strain. The strain name is the file name up to the first dot.
+    /// `diff.tsv` of `diff_aln`, outputs of `summarize_annotation` (with or without `--effects`), and VCF are accepted.
+    /// `diff.tsv` is split into strains by its `Strain` column.
+    #[arg(short, long, num_args = 1.., required = true)]
+    variants: Vec<PathBuf>,
+    /// Prefix of the output path.
+    #[arg(short, long)]
+    prefix: PathBuf,
+}
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::*;
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+    let mut strains = vec![];
+    let mut variants = vec![];
+    for path in args.variants.iter() {
+        let strain = mito_check::strain_name(path);
+        let mut strain_to_vars: BTreeMap<String, HashSet<Variant>> = BTreeMap::new();
+        for (var_strain, var) in parse_variants(path)? {
+            let var_strain = var_strain.unwrap_or_else(|| strain.clone());
+            strain_to_vars.entry(var_strain).or_default().insert(var);
+        }
+        if strain_to_vars.is_empty() {
+            strain_to_vars.insert(strain, HashSet::new());
+        }
+        for (strain, vars) in strain_to_vars {
+            eprintln!("{strain}\t{}", vars.len());
+            strains.push(strain);
+            variants.push(vars);
+        }
+    }
+    // (Reference, position) -> alleles in each strain.
+    let mut sites: BTreeMap<(&str, usize), Vec<Alleles>> = BTreeMap::new();
+    for (i, vars) in variants.iter().enumerate() {
+        for (refr, pos, ref_allele, alt_allele) in vars.iter() {
+            let site = sites
+                .entry((refr.as_str(), *pos))
+                .or_insert_with(|| vec![BTreeSet::new(); strains.len()]);
+            site[i].insert((ref_allele.as_str(), alt_allele.as_str()));
+        }
+    }
+    {
+        let mut filename = args.prefix.clone();
+        filename.push("variant_matrix.tsv");
+        let mut wtr = std::fs::File::create(&filename).map(BufWriter::new)?;
+        let header = "Refr\tPosition\tNumStrains\tNumAlleles\tClass";
+        writeln!(&mut wtr, "{header}\t{}", strains.join("\t"))?;
+        for ((refr, pos), alleles) in sites.iter() {
+            let num_strains = alleles.iter().filter(|a| !a.is_empty()).count();
+            let num_alleles = alleles.iter().flatten().collect::<HashSet<_>>().len();
+            let class = match num_strains {
+                1 => "Private",
+                _ => "Shared",
+            };
+            let cells: Vec<_> = alleles
+                .iter()
+                .map(|alleles| match alleles.is_empty() {
+                    true => ".".to_string(),
+                    false => {
+                        let alleles: Vec<_> =
+                            alleles.iter().map(|(r, a)| format!("{r}>{a}")).collect();
+                        alleles.join(",")
+                    }
+                })
+                .collect();
+            writeln!(
+                &mut wtr,
+                "{refr}\t{pos}\t{num_strains}\t{num_alleles}\t{class}\t{}",
+                cells.join("\t")
+            )?;
+        }
+    }
+    {
+        let mut filename = args.prefix.clone();
+        filename.push("strain_distance.tsv");
+        let mut wtr = std::fs::File::create(&filename).map(BufWriter::new)?;
+        writeln!(&mut wtr, "Strain\t{}", strains.join("\t"))?;
+        for (strain, xs) in strains.iter().zip(variants.iter()) {
+            let distances: Vec<_> = variants
+                .iter()
+                .map(|ys| jaccard_distance(xs, ys).to_string())
+                .collect();
+            writeln!(&mut wtr, "{strain}\t{}", distances.join("\t"))?;
+        }
+    }
+    // The number of private and shared variants in each strain.
+    println!("Strain\tPrivate\tShared");
+    for (i, strain) in strains.iter().enumerate() {
+        let (mut private, mut shared) = (0, 0);
+        for alleles in sites.values() {
+            for allele in alleles[i].iter() {
+                match alleles.iter().filter(|a| a.contains(allele)).count() {
+                    1 => private += 1,
+                    _ => shared += 1,
+                }
+            }
+        }
+        println!("{strain}\t{private}\t{shared}");
+    }
+    Ok(())
+}
+
+// Pairs of the reference allele and the alternative allele.
+type Alleles<'a> = BTreeSet<(&'a str, &'a str)>;
+
+fn jaccard_distance(xs: &HashSet<Variant>, ys: &HashSet<Variant>) -> f64 {
+    let union = xs.union(ys).count();
+    let intersection = xs.intersection(ys).count();
+    match union {
+        0 => 0f64,
+        _ => 1f64 - intersection as f64 / union as f64,
+    }
+}
+
+// Reference, position (0-based), reference allele, alternative allele. Empty alleles are `-`.
+// When the alleles are not available, the reference allele is `.` and the alternative allele is the type of the variant.
+type Variant = (String, usize, String, String);
+// Variants with their strains. The strain is `None` if the file does not tell it.
+fn parse_variants(path: &std::path::Path) -> std::io::Result<Vec<(Option<String>, Variant)>> {
+    let lines: Vec<_> = std::fs::File::open(path)
+        .map(BufReader::new)?
+        .lines()
+        .filter_map(|l| l.ok())
+        .collect();
+    let header = lines.first().map(|l| l.as_str()).unwrap_or("");
+    let variants: Vec<_> = if header.starts_with("##fileformat=VCF") {
+        lines
+            .iter()
+            .filter(|l| !l.starts_with('#'))
+            .flat_map(|l| from_vcf(l))
+            .map(|var| (None, var))
+            .collect()
+    } else if header.starts_with("Query\tQpos\tRefr\tRpos") {
+        lines.iter().skip(1).filter_map(|l| from_diff(l)).collect()
+    } else if header.starts_with("Refr\tPosition\tRef\tAlt") {
+        lines
+            .iter()
+            .skip(1)
+            .filter_map(|l| from_effects(l))
+            .map(|var| (None, var))
+            .collect()
+    } else {
+        lines
+            .iter()
+            .filter_map(|l| from_summary(l))
+            .map(|var| (None, var))
+            .collect()
+    };
+    Ok(variants)
+}
+
+fn to_allele(allele: &str) -> String {
+    match allele {
+        "" | "." => "-".to_string(),
+        _ => allele.to_string(),
+    }
+}
+
+// A line of `diff.tsv`, with the strain of the query.
+fn from_diff(line: &str) -> Option<(Option<String>, Variant)> {
+    let fields: Vec<_> = line.split('\t').collect();
+    let refr = fields.get(2)?.to_string();
+    let pos: usize = fields.get(3)?.parse().ok()?;
+    let strain = fields.get(12).map(|strain| strain.to_string());
+    let variant = match (fields.get(6), fields.get(7)) {
+        (Some(&r), Some(&a)) if r != "." => (refr, pos, r.to_string(), a.to_string()),
+        _ => (
+            refr,
+            pos,
+            ".".to_string(),
+            format!("{}{}", fields[4], fields[5]),
+        ),
+    };
+    Some((strain, variant))
+}
+
+// A line of the output of `summarize_annotation --effects`.
+fn from_effects(line: &str) -> Option<Variant> {
+    let fields: Vec<_> = line.split('\t').collect();
+    let refr = fields.first()?.to_string();
+    let pos: usize = fields.get(1)?.parse().ok()?;
+    Some((
+        refr,
+        pos,
+        to_allele(fields.get(2)?),
+        to_allele(fields.get(3)?),
+    ))
+}
+
+// A line of the output of `summarize_annotation`.
+fn from_summary(line: &str) -> Option<Variant> {
+    let fields: Vec<_> = line.split('\t').collect();
+    let refr = fields.first()?.to_string();
+    let pos: usize = fields.get(1)?.parse().ok()?;
+    Some((refr, pos, ".".to_string(), fields.get(2)?.to_string()))
+}
+
+// A record of VCF. Multiple alternative alleles are split, and the padding base of indels is removed.
+fn from_vcf(line: &str) -> Vec<Variant> {
+    let fields: Vec<_> = line.split('\t').collect();
+    let pos: usize = match fields.get(1).and_then(|pos| pos.parse().ok()) {
+        Some(pos) => pos,
+        None => return vec![],
+    };
+    let (refr, ref_allele) = match (fields.first(), fields.get(3)) {
+        (Some(refr), Some(ref_allele)) => (refr.to_string(), *ref_allele),
+        _ => return vec![],
+    };
+    let alts = fields
+        .get(4)
+        .map(|alt| alt.split(','))
+        .into_iter()
+        .flatten();
+    alts.filter(|alt| *alt != "." && *alt != "*")
+        .filter_map(|alt| {
+            let padded = ref_allele.len() != alt.len()
+                && ref_allele.as_bytes().first() == alt.as_bytes().first();
+            match padded {
+                true => Some((
+                    refr.clone(),
+                    pos,
+                    to_allele(&ref_allele[1..]),
+                    to_allele(&alt[1..]),
+                )),
+                // POS 0 is a telomere, which is not a variant of a base.
+                false => Some((
+                    refr.clone(),
+                    pos.checked_sub(1)?,
+                    to_allele(ref_allele),
+                    to_allele(alt),
+                )),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,431 @@
+//! Coding sequences, genetic codes, and the effect of variants on proteins.
+use crate::gff::Feature;
+use std::collections::{HashMap, HashSet};
+
+/// NCBI translation tables. Codons are indexed in TCAG order.
+#[derive(Debug, Clone)]
+pub struct GeneticCode {
+    amino_acids: &'static [u8; 64],
+    starts: &'static [&'static [u8; 3]],
+}
+
+const STANDARD: &[u8; 64] = b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+const VERTEBRATE_MITO: &[u8; 64] =
+    b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSS**VVVVAAAADDEEGGGG";
+const YEAST_MITO: &[u8; 64] = b"FFLLSSSSYY**CCWWTTTTPPPPHHQQRRRRIIMMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+const MOLD_MITO: &[u8; 64] = b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+const INVERTEBRATE_MITO: &[u8; 64] =
+    b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSSSSVVVVAAAADDEEGGGG";
+const FLATWORM_MITO: &[u8; 64] =
+    b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNNKSSSSVVVVAAAADDEEGGGG";
+
+impl GeneticCode {
+    /// Return the NCBI translation table `table`. Plant mitochondria use the standard code (1).
+    pub fn new(table: usize) -> Option<Self> {
+        let (amino_acids, starts): (_, &'static [&'static [u8; 3]]) = match table {
+            1 => (STANDARD, &[b"TTG", b"CTG", b"ATG"]),
+            2 => (VERTEBRATE_MITO, &[b"ATT", b"ATC", b"ATA", b"ATG", b"GTG"]),
+            3 => (YEAST_MITO, &[b"ATA", b"ATG", b"GTG"]),
+            4 => (
+                MOLD_MITO,
+                &[
+                    b"TTA", b"TTG", b"CTG", b"ATT", b"ATC", b"ATA", b"ATG", b"GTG",
+                ],
+            ),
+            5 => (
+                INVERTEBRATE_MITO,
+                &[b"TTG", b"ATT", b"ATC", b"ATA", b"ATG", b"GTG"],
+            ),
+            9 => (FLATWORM_MITO, &[b"ATG", b"GTG"]),
+            11 => (
+                STANDARD,
+                &[b"TTG", b"CTG", b"ATT", b"ATC", b"ATA", b"ATG", b"GTG"],
+            ),
+            _ => return None,
+        };
+        Some(Self {
+            amino_acids,
+            starts,
+        })
+    }
+    /// Translate a codon. Return `X` if the codon has an ambiguous base.
+    pub fn translate(&self, codon: &[u8]) -> u8 {
+        let mut idx = 0;
+        for &base in codon.iter() {
+            let bit = match base.to_ascii_uppercase() {
+                b'T' | b'U' => 0,
+                b'C' => 1,
+                b'A' => 2,
+                b'G' => 3,
+                _ => return b'X',
+            };
+            idx = (idx << 2) | bit;
+        }
+        match codon.len() {
+            3 => self.amino_acids[idx],
+            _ => b'X',
+        }
+    }
+    pub fn is_start(&self, codon: &[u8]) -> bool {
+        let codon: Vec<_> = codon.iter().map(|b| b.to_ascii_uppercase()).collect();
+        self.starts.iter().any(|start| start.as_slice() == codon)
+    }
+}
+
+/// A part of a coding sequence. 0-based, half-open interval on `seqid`.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub seqid: String,
+    pub start: usize,
+    pub end: usize,
+    pub is_forward: bool,
+}
+
+/// A coding sequence, possibly spliced from several segments, even from both strands (trans-splicing).
+#[derive(Debug, Clone)]
+pub struct CodingSequence {
+    pub id: String,
+    pub gene: String,
+    /// Segments in the order of the transcript.
+    pub segments: Vec<Segment>,
+}
+
+/// Join `CDS` features by their parents.
+/// Parts are ordered by the `part` attribute if every part has it, or by the coordinate if all parts are on the same strand.
+/// Otherwise, the order in the file is the order in the transcript.
+pub fn coding_sequences(features: &[Feature]) -> Vec<CodingSequence> {
+    let mut order = vec![];
+    let mut cdss: HashMap<_, Vec<&Feature>> = HashMap::new();
+    for feature in features.iter().filter(|f| f.feature_type == "CDS") {
+        let key = match feature.parent().or_else(|| feature.id()) {
+            Some(key) => key.to_string(),
+            None => continue,
+        };
+        if !cdss.contains_key(&key) {
+            order.push(key.clone());
+        }
+        cdss.entry(key).or_default().push(feature);
+    }
+    order
+        .into_iter()
+        .map(|key| {
+            let mut parts = cdss.remove(&key).unwrap();
+            let parts_numbers: Option<Vec<usize>> = parts
+                .iter()
+                .map(|f| f.attribute("part")?.split('/').next()?.parse().ok())
+                .collect();
+            let is_same_strand = parts
+                .iter()
+                .all(|f| f.seqid == parts[0].seqid && f.strand == parts[0].strand);
+            if let Some(numbers) = parts_numbers {
+                let mut numbered: Vec<_> = std::iter::zip(numbers, parts).collect();
+                numbered.sort_by_key(|x| x.0);
+                parts = numbered.into_iter().map(|x| x.1).collect();
+            } else if is_same_strand {
+                parts.sort_by_key(|f| f.start);
+                if parts[0].strand == Some(false) {
+                    parts.reverse();
+                }
+            }
+            let gene = parts[0]
+                .attribute("gene")
+                .or_else(|| parts[0].name())
+                .unwrap_or(&key)
+                .to_string();
+            let segments = parts
+                .iter()
+                .map(|f| Segment {
+                    seqid: f.seqid.clone(),
+                    start: f.start - 1,
+                    end: f.end,
+                    is_forward: f.strand != Some(false),
+                })
+                .collect();
+            CodingSequence {
+                id: key,
+                gene,
+                segments,
+            }
+        })
+        .collect()
+}
+
+/// Effects of a variant on the protein.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    Synonymous,
+    Missense,
+    Nonsense,
+    StartLost,
+    StopLost,
+    Frameshift,
+    InframeInsertion,
+    InframeDeletion,
+}
+
+impl std::fmt::Display for Effect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let effect = match self {
+            Effect::Synonymous => "Synonymous",
+            Effect::Missense => "Missense",
+            Effect::Nonsense => "Nonsense",
+            Effect::StartLost => "StartLost",
+            Effect::StopLost => "StopLost",
+            Effect::Frameshift => "Frameshift",
+            Effect::InframeInsertion => "InframeIns",
+            Effect::InframeDeletion => "InframeDel",
+        };
+        write!(f, "{effect}")
+    }
+}
+
+/// Predicted effect of a variant. Codons and amino acids are empty if they are not determined (indels).
+#[derive(Debug, Clone)]
+pub struct Prediction {
+    /// 0-based position in the coding sequence.
+    pub cds_position: usize,
+    pub ref_codon: Vec<u8>,
+    pub alt_codon: Vec<u8>,
+    pub ref_aa: Option<u8>,
+    pub alt_aa: Option<u8>,
+    pub effect: Effect,
+}
+
+impl CodingSequence {
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|s| s.end - s.start).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Return the index in this coding sequence and the direction of the 0-based `position`.
+    pub fn locate(&self, seqid: &str, position: usize) -> Option<(usize, bool)> {
+        let mut offset = 0;
+        for seg in self.segments.iter() {
+            if seg.seqid == seqid && seg.start <= position && position < seg.end {
+                let idx = match seg.is_forward {
+                    true => position - seg.start,
+                    false => seg.end - 1 - position,
+                };
+                return Some((offset + idx, seg.is_forward));
+            }
+            offset += seg.end - seg.start;
+        }
+        None
+    }
+    /// Spliced sequence. Return `None` if some segment is out of the genomes.
+    pub fn sequence(&self, genomes: &HashMap<String, &[u8]>) -> Option<Vec<u8>> {
+        let mut seq = Vec::with_capacity(self.len());
+        for seg in self.segments.iter() {
+            let genome = genomes.get(&seg.seqid)?;
+            let part = genome.get(seg.start..seg.end)?;
+            match seg.is_forward {
+                true => seq.extend(part.iter().map(|b| b.to_ascii_uppercase())),
+                false => seq.extend(
+                    bio_utils::revcmp(part)
+                        .iter()
+                        .map(|b| b.to_ascii_uppercase()),
+                ),
+            }
+        }
+        Some(seq)
+    }
+    /// C-to-U editing at the given sites (0-based position on the genome).
+    pub fn apply_editing(&self, seq: &mut [u8], sites: &HashSet<(String, usize)>) {
+        for (seqid, position) in sites.iter() {
+            if let Some((idx, _)) = self.locate(seqid, *position) {
+                if seq[idx] == b'C' {
+                    seq[idx] = b'T';
+                }
+            }
+        }
+    }
+    /// Predict the effect of a variant, where `seq` is the (edited) sequence of this coding sequence.
+    /// Alleles are on the forward strand of the genome. An empty `refr` is an insertion before `position`,
+    /// an empty `alt` is a deletion of `refr.len()` bases from `position`.
+    pub fn predict(
+        &self,
+        seq: &[u8],
+        code: &GeneticCode,
+        seqid: &str,
+        position: usize,
+        (refr, alt): (&[u8], &[u8]),
+    ) -> Option<Prediction> {
+        let codon_of = |idx: usize| {
+            let start = idx / 3 * 3;
+            seq.get(start..start + 3).map(|c| c.to_vec())
+        };
+        let is_in_stop = |idx: usize| {
+            let last = seq.len() / 3 * 3;
+            3 <= last
+                && last - 3 <= idx
+                && seq.get(last - 3..last).map(|c| code.translate(c)) == Some(b'*')
+        };
+        let is_in_start = |idx: usize| idx < 3 && seq.len() >= 3 && code.is_start(&seq[..3]);
+        if refr.len() == 1 && alt.len() == 1 {
+            let (idx, is_forward) = self.locate(seqid, position)?;
+            let ref_codon = codon_of(idx)?;
+            let mut alt_codon = ref_codon.clone();
+            alt_codon[idx % 3] = match is_forward {
+                true => alt[0].to_ascii_uppercase(),
+                false => complement(alt[0].to_ascii_uppercase()),
+            };
+            let (ref_aa, alt_aa) = (code.translate(&ref_codon), code.translate(&alt_codon));
+            let effect = if idx < 3 && code.is_start(&ref_codon) && !code.is_start(&alt_codon) {
+                Effect::StartLost
+            } else if ref_aa == b'*' && alt_aa != b'*' {
+                Effect::StopLost
+            } else if ref_aa != b'*' && alt_aa == b'*' {
+                Effect::Nonsense
+            } else if ref_aa == alt_aa {
+                Effect::Synonymous
+            } else {
+                Effect::Missense
+            };
+            Some(Prediction {
+                cds_position: idx,
+                ref_codon,
+                alt_codon,
+                ref_aa: Some(ref_aa),
+                alt_aa: Some(alt_aa),
+                effect,
+            })
+        } else if refr.is_empty() {
+            // Both flanking bases should be in this coding sequence.
+            let (before, _) = self.locate(seqid, position.checked_sub(1)?)?;
+            let (after, _) = self.locate(seqid, position)?;
+            if before.max(after) - before.min(after) != 1 {
+                return None;
+            }
+            let idx = before.max(after);
+            let effect = match alt.len() % 3 {
+                0 => Effect::InframeInsertion,
+                _ => Effect::Frameshift,
+            };
+            let ref_codon = codon_of(idx).unwrap_or_default();
+            let ref_aa = (!ref_codon.is_empty()).then(|| code.translate(&ref_codon));
+            Some(Prediction {
+                cds_position: idx,
+                ref_codon,
+                alt_codon: vec![],
+                ref_aa,
+                alt_aa: None,
+                effect,
+            })
+        } else {
+            let hits: Vec<_> = (position..position + refr.len())
+                .filter_map(|pos| self.locate(seqid, pos).map(|x| x.0))
+                .collect();
+            let idx = *hits.iter().min()?;
+            let effect = if hits.iter().any(|&i| is_in_start(i)) {
+                Effect::StartLost
+            } else if hits.iter().any(|&i| is_in_stop(i)) {
+                Effect::StopLost
+            } else if hits.len() % 3 == 0 {
+                Effect::InframeDeletion
+            } else {
+                Effect::Frameshift
+            };
+            let ref_codon = codon_of(idx).unwrap_or_default();
+            let ref_aa = (!ref_codon.is_empty()).then(|| code.translate(&ref_codon));
+            Some(Prediction {
+                cds_position: idx,
+                ref_codon,
+                alt_codon: vec![],
+                ref_aa,
+                alt_aa: None,
+                effect,
+            })
+        }
+    }
+}
+
+fn complement(base: u8) -> u8 {
+    match base {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        x => x,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn forward_cds(len: usize) -> CodingSequence {
+        let segments = vec![Segment {
+            seqid: "chr".to_string(),
+            start: 0,
+            end: len,
+            is_forward: true,
+        }];
+        CodingSequence {
+            id: "cds".to_string(),
+            gene: "gene".to_string(),
+            segments,
+        }
+    }
+    #[test]
+    fn translate() {
+        let (standard, mito) = (GeneticCode::new(1).unwrap(), GeneticCode::new(2).unwrap());
+        assert_eq!(standard.translate(b"ATG"), b'M');
+        assert_eq!(standard.translate(b"tga"), b'*');
+        assert_eq!(standard.translate(b"AGA"), b'R');
+        assert_eq!(standard.translate(b"ANA"), b'X');
+        assert_eq!(mito.translate(b"TGA"), b'W');
+        assert_eq!(mito.translate(b"AGA"), b'*');
+        assert_eq!(mito.translate(b"ATA"), b'M');
+        assert!(GeneticCode::new(6).is_none());
+    }
+    #[test]
+    fn is_start() {
+        let (standard, mito) = (GeneticCode::new(1).unwrap(), GeneticCode::new(2).unwrap());
+        assert!(standard.is_start(b"ATG") && standard.is_start(b"ctg"));
+        assert!(!standard.is_start(b"ATA"));
+        assert!(mito.is_start(b"ATA") && mito.is_start(b"GTG"));
+        assert!(!mito.is_start(b"CTG"));
+    }
+    #[test]
+    fn locate_reverse_strand() {
+        let mut cds = forward_cds(6);
+        cds.segments.push(Segment {
+            seqid: "chr".to_string(),
+            start: 10,
+            end: 16,
+            is_forward: false,
+        });
+        assert_eq!(cds.locate("chr", 5), Some((5, true)));
+        assert_eq!(cds.locate("chr", 15), Some((6, false)));
+        assert_eq!(cds.locate("chr", 10), Some((11, false)));
+        assert_eq!(cds.locate("chr", 8), None);
+        assert_eq!(cds.locate("chrM", 0), None);
+    }
+    #[test]
+    fn predict() {
+        // M A W *
+        let seq = b"ATGGCATGGTAA";
+        let cds = forward_cds(seq.len());
+        let code = GeneticCode::new(1).unwrap();
+        let effect = |position: usize, refr: &[u8], alt: &[u8]| {
+            let prediction = cds.predict(seq, &code, "chr", position, (refr, alt));
+            prediction.map(|p| p.effect)
+        };
+        assert_eq!(effect(5, b"A", b"G"), Some(Effect::Synonymous));
+        assert_eq!(effect(3, b"G", b"A"), Some(Effect::Missense));
+        assert_eq!(effect(8, b"G", b"A"), Some(Effect::Nonsense));
+        assert_eq!(effect(2, b"G", b"C"), Some(Effect::StartLost));
+        assert_eq!(effect(9, b"T", b"C"), Some(Effect::StopLost));
+        assert_eq!(effect(4, b"C", b""), Some(Effect::Frameshift));
+        assert_eq!(effect(4, b"", b"AA"), Some(Effect::Frameshift));
+        assert_eq!(effect(3, b"GCA", b""), Some(Effect::InframeDeletion));
+        assert_eq!(effect(20, b"A", b"G"), None);
+        let prediction = cds.predict(seq, &code, "chr", 3, (b"G", b"A")).unwrap();
+        assert_eq!(prediction.ref_codon, b"GCA".to_vec());
+        assert_eq!(prediction.alt_codon, b"ACA".to_vec());
+        assert_eq!(
+            (prediction.ref_aa, prediction.alt_aa),
+            (Some(b'A'), Some(b'T'))
+        );
+    }
+}
@@ -0,0 +1,93 @@
+//! Minimal GFF3 reader.
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// A feature line in a GFF3 file. Coordinates are 1-based and inclusive, as in the file.
+#[derive(Debug, Clone)]
+pub struct Feature {
+    pub seqid: String,
+    pub source: String,
+    pub feature_type: String,
+    pub start: usize,
+    pub end: usize,
+    pub score: Option<f64>,
+    /// `None` if the strand is not given (`.` or `?`).
+    pub strand: Option<bool>,
+    pub phase: Option<usize>,
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Feature {
+    /// Parse a line. Return `None` if the line is a comment or a malformed line.
+    pub fn new(line: &str) -> Option<Self> {
+        if line.starts_with('#') {
+            return None;
+        }
+        let fields: Vec<_> = line.split('\t').collect();
+        if fields.len() < 9 {
+            return None;
+        }
+        let start: usize = fields[3].parse().ok()?;
+        let end: usize = fields[4].parse().ok()?;
+        let score = fields[5].parse().ok();
+        let strand = match fields[6] {
+            "+" => Some(true),
+            "-" => Some(false),
+            _ => None,
+        };
+        let phase = fields[7].parse().ok();
+        let attributes = fields[8]
+            .trim_end_matches(';')
+            .split(';')
+            .filter_map(|kv| kv.split_once('='))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Some(Self {
+            seqid: fields[0].to_string(),
+            source: fields[1].to_string(),
+            feature_type: fields[2].to_string(),
+            start,
+            end,
+            score,
+            strand,
+            phase,
+            attributes,
+        })
+    }
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+    pub fn id(&self) -> Option<&str> {
+        self.attribute("ID")
+    }
+    /// The first parent, if any.
+    pub fn parent(&self) -> Option<&str> {
+        self.attribute("Parent")
+            .map(|p| p.split(',').next().unwrap())
+    }
+    /// Human readable name. `Name`, then `gene`, then `ID`.
+    pub fn name(&self) -> Option<&str> {
+        self.attribute("Name")
+            .or_else(|| self.attribute("gene"))
+            .or_else(|| self.id())
+    }
+    /// Return true if the 0-based position is in this feature.
+    pub fn contains(&self, seqid: &str, position: usize) -> bool {
+        self.seqid == seqid && self.start <= position + 1 && position < self.end
+    }
+}
+
+/// Parse GFF3 file into features. Comments and malformed lines are skipped.
+pub fn parse_into_vec<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<Feature>> {
+    let features = std::fs::File::open(path)
+        .map(BufReader::new)?
+        .lines()
+        .map_while(Result::ok)
+        .take_while(|l| !l.starts_with("##FASTA"))
+        .filter_map(|l| Feature::new(&l))
+        .collect();
+    Ok(features)
+}
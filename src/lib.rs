@@ -1,3 +1,10 @@
+pub mod alignment;
+pub mod coding;
+pub mod gff;
+pub mod pairwise;
+pub mod stats;
+pub mod theme;
+
 use std::collections::HashMap;
 pub fn count_kmers(genomes: &[bio_utils::fasta::Record], k: usize) -> HashMap<u64, u32> {
     let mut counts: HashMap<_, u32> = HashMap::new();
@@ -10,6 +17,50 @@ pub fn count_kmers(genomes: &[bio_utils::fasta::Record], k: usize) -> HashMap<u6
     counts
 }
 
+/// The strain name of a file, i.e., the file name up to the first dot. `-` if the path does not have a file name.
+pub fn strain_name(path: &std::path::Path) -> String {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('.').next())
+        .unwrap_or("-")
+        .to_string()
+}
+
+/// A repetitive region from `annotate_repetitive_kmers`. Contig, start, end (0-based, half-open), and the mean count of the k-mers.
+pub type Repeat = (String, usize, usize, u32);
+
+/// Parse the output of `annotate_repetitive_kmers`. The header and malformed lines are skipped.
+pub fn load_repeats(path: &std::path::Path) -> std::io::Result<Vec<Repeat>> {
+    use std::io::BufRead;
+    // ID, Start, End (the start of the last k-mer), Count, Seq.
+    let repeats = std::fs::File::open(path)
+        .map(std::io::BufReader::new)?
+        .lines()
+        .map_while(Result::ok)
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split('\t').collect();
+            let start: usize = fields.get(1)?.parse().ok()?;
+            let count: u32 = fields.get(3)?.parse().ok()?;
+            let length = fields.get(4)?.len();
+            Some((fields[0].to_string(), start, start + length, count))
+        })
+        .collect();
+    Ok(repeats)
+}
+
+/// Count positions in windows of `window_size` bp. Keys are the indices of the windows, i.e., `position / window_size`.
+pub fn count_in_windows<I: IntoIterator<Item = usize>>(
+    positions: I,
+    window_size: usize,
+) -> HashMap<usize, usize> {
+    let mut counts: HashMap<_, usize> = HashMap::new();
+    for pos in positions {
+        *counts.entry(pos / window_size).or_default() += 1;
+    }
+    counts
+}
+
 pub fn back_to_seq(kmer: u64, k: usize) -> Vec<u8> {
     (0..k)
         .map(|offset| {
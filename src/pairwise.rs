@@ -0,0 +1,103 @@
+//! Blocks of pairwise alignments in MAF or Last's TSV (`maf-convert tab`) format.
+use bio_utils::maf;
+
+/// An alignment between the reference and the query. 0-based, half-open, and on the forward strand of each sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub refr: String,
+    pub rstart: usize,
+    pub rend: usize,
+    pub rlen: usize,
+    pub query: String,
+    pub qstart: usize,
+    pub qend: usize,
+    pub qlen: usize,
+    /// Whether the reference and the query are aligned on the same strand.
+    pub is_forward: bool,
+}
+
+impl Block {
+    /// The first sequence of the record is the reference, and the second is the query.
+    pub fn from_maf(record: &maf::Record) -> Option<Self> {
+        let sequences = record.sequence();
+        let (refr, query) = (sequences.first()?, sequences.get(1)?);
+        let (rlen, qlen) = (refr.src_size() as usize, query.src_size() as usize);
+        let (rstart, rend) = to_forward(
+            refr.start() as usize,
+            refr.length() as usize,
+            rlen,
+            refr.is_forward(),
+        );
+        let (qstart, qend) = to_forward(
+            query.start() as usize,
+            query.length() as usize,
+            qlen,
+            query.is_forward(),
+        );
+        Some(Self {
+            refr: refr.name().to_string(),
+            rstart,
+            rend,
+            rlen,
+            query: query.name().to_string(),
+            qstart,
+            qend,
+            qlen,
+            is_forward: refr.is_forward() == query.is_forward(),
+        })
+    }
+    /// Parse a line of Last's TSV. Score, name1, start1, alnSize1, strand1, seqSize1, name2, start2, alnSize2, strand2, seqSize2, blocks.
+    pub fn from_tab(line: &str) -> Option<Self> {
+        let fields: Vec<_> = line.split('\t').collect();
+        let parse = |i: usize| -> Option<usize> { fields.get(i)?.parse().ok() };
+        let (rlen, qlen) = (parse(5)?, parse(10)?);
+        let (r_forward, q_forward) = (*fields.get(4)? == "+", *fields.get(9)? == "+");
+        let (rstart, rend) = to_forward(parse(2)?, parse(3)?, rlen, r_forward);
+        let (qstart, qend) = to_forward(parse(7)?, parse(8)?, qlen, q_forward);
+        Some(Self {
+            refr: fields[1].to_string(),
+            rstart,
+            rend,
+            rlen,
+            query: fields[6].to_string(),
+            qstart,
+            qend,
+            qlen,
+            is_forward: r_forward == q_forward,
+        })
+    }
+}
+
+/// Convert an interval of `len` bp from `start` on the given strand of a sequence of `size` bp into the forward strand.
+pub fn to_forward(start: usize, len: usize, size: usize, is_forward: bool) -> (usize, usize) {
+    match is_forward {
+        true => (start, start + len),
+        false => (size - start - len, size - start),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn forward() {
+        assert_eq!(to_forward(10, 5, 100, true), (10, 15));
+        assert_eq!(to_forward(10, 5, 100, false), (85, 90));
+        assert_eq!(to_forward(0, 100, 100, false), (0, 100));
+    }
+    #[test]
+    fn tab() {
+        let line = "300\tchrM\t10\t20\t+\t100\tctg1\t5\t20\t-\t50\t20";
+        let block = Block::from_tab(line).unwrap();
+        assert_eq!(
+            (block.refr.as_str(), block.rstart, block.rend),
+            ("chrM", 10, 30)
+        );
+        assert_eq!(
+            (block.query.as_str(), block.qstart, block.qend),
+            ("ctg1", 25, 45)
+        );
+        assert_eq!((block.rlen, block.qlen, block.is_forward), (100, 50, false));
+        assert!(Block::from_tab("300\tchrM\t10").is_none());
+    }
+}
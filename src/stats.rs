@@ -0,0 +1,132 @@
+//! Tails of binomial-type distributions, used to test allele counts.
+
+/// P(X >= k) where X ~ Binom(n, p).
+pub fn binomial_upper_tail(k: usize, n: usize, p: f64) -> f64 {
+    let tail: f64 = (k..=n)
+        .map(|i| (ln_choose(n, i) + i as f64 * p.ln() + (n - i) as f64 * (1f64 - p).ln()).exp())
+        .sum();
+    tail.min(1f64)
+}
+
+/// P-value of the two-sided binomial test, i.e., the sum of P(X = i) not larger than P(X = k) where X ~ Binom(n, p).
+pub fn binomial_two_sided(k: usize, n: usize, p: f64) -> f64 {
+    let ln_prob = |i: usize| ln_choose(n, i) + i as f64 * p.ln() + (n - i) as f64 * (1f64 - p).ln();
+    // Relative tolerance for ties, as in R's binom.test.
+    let threshold = ln_prob(k) + 1e-7f64.ln_1p();
+    let p_value: f64 = (0..=n)
+        .map(ln_prob)
+        .filter(|&lp| lp <= threshold)
+        .map(f64::exp)
+        .sum();
+    p_value.min(1f64)
+}
+
+/// P(X >= k) where X ~ BetaBinom(n, a, b) with mean p and overdispersion rho, i.e., a = p(1-rho)/rho, b = (1-p)(1-rho)/rho.
+pub fn beta_binomial_upper_tail(k: usize, n: usize, p: f64, rho: f64) -> f64 {
+    let a = p * (1f64 - rho) / rho;
+    let b = (1f64 - p) * (1f64 - rho) / rho;
+    let ln_beta = |x: f64, y: f64| ln_gamma(x) + ln_gamma(y) - ln_gamma(x + y);
+    let tail: f64 = (k..=n)
+        .map(|i| {
+            (ln_choose(n, i) + ln_beta(i as f64 + a, (n - i) as f64 + b) - ln_beta(a, b)).exp()
+        })
+        .sum();
+    tail.min(1f64)
+}
+
+/// Distribution of the number of successes in independent Bernoulli trials with probabilities `probs`.
+/// The k-th element is the probability of exactly k successes.
+pub fn poisson_binomial(probs: &[f64]) -> Vec<f64> {
+    let mut dist = vec![0f64; probs.len() + 1];
+    dist[0] = 1f64;
+    for (i, &p) in probs.iter().enumerate() {
+        for k in (1..=i + 1).rev() {
+            dist[k] = dist[k] * (1f64 - p) + dist[k - 1] * p;
+        }
+        dist[0] *= 1f64 - p;
+    }
+    dist
+}
+
+fn ln_choose(n: usize, i: usize) -> f64 {
+    ln_gamma(n as f64 + 1f64) - ln_gamma(i as f64 + 1f64) - ln_gamma((n - i) as f64 + 1f64)
+}
+
+/// Lanczos approximation of ln(Gamma(x)) for positive x.
+pub fn ln_gamma(x: f64) -> f64 {
+    const G: f64 = 7f64;
+    const COEFS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1f64 - x);
+    }
+    let x = x - 1f64;
+    let sum: f64 = COEFS
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, c)| c / (x + i as f64))
+        .sum::<f64>()
+        + COEFS[0];
+    let t = x + G + 0.5;
+    0.5 * (2f64 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn assert_close(x: f64, y: f64) {
+        assert!((x - y).abs() < 1e-9, "{x} != {y}");
+    }
+    #[test]
+    fn gamma() {
+        assert_close(ln_gamma(1f64), 0f64);
+        assert_close(ln_gamma(5f64), 24f64.ln());
+        assert_close(ln_gamma(0.5), std::f64::consts::PI.sqrt().ln());
+    }
+    #[test]
+    fn binomial() {
+        assert_close(binomial_upper_tail(0, 10, 0.3), 1f64);
+        assert_close(binomial_upper_tail(3, 10, 0.5), 968f64 / 1024f64);
+        assert_close(binomial_upper_tail(2, 5, 0.1), 0.08146);
+    }
+    #[test]
+    fn two_sided() {
+        // Values of R's binom.test.
+        assert_close(binomial_two_sided(3, 10, 0.5), 0.34375);
+        assert_close(binomial_two_sided(0, 10, 0.5), 2f64 / 1024f64);
+        assert_close(binomial_two_sided(5, 10, 0.5), 1f64);
+    }
+    #[test]
+    fn beta_binomial() {
+        // With a = b = 1, X is uniform on [0, n].
+        let rho = 1f64 / 3f64;
+        assert_close(beta_binomial_upper_tail(3, 10, 0.5, rho), 8f64 / 11f64);
+        assert_close(beta_binomial_upper_tail(0, 10, 0.5, rho), 1f64);
+        // Small overdispersion is close to the binomial.
+        let diff = beta_binomial_upper_tail(3, 10, 0.5, 1e-8) - 968f64 / 1024f64;
+        assert!(diff.abs() < 1e-6);
+    }
+    #[test]
+    fn bernoulli_trials() {
+        let dist = poisson_binomial(&[0.5, 0.5]);
+        dist.iter()
+            .zip([0.25, 0.5, 0.25])
+            .for_each(|(&x, y)| assert_close(x, y));
+        let dist = poisson_binomial(&[0.1, 0.2, 0.3]);
+        dist.iter()
+            .zip([0.504, 0.398, 0.092, 0.006])
+            .for_each(|(&x, y)| assert_close(x, y));
+        assert_eq!(poisson_binomial(&[]), vec![1f64]);
+    }
+}
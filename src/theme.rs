@@ -0,0 +1,182 @@
+//! Layout and colours of the figures drawn by `convert_sam_to_svg` and `dot_plot`, optionally loaded from a TOML file.
+use std::path::Path;
+
+/// Layout and colours of the figures. Lengths are in pixels.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub width: usize,
+    pub height_per_reads: usize,
+    pub del_stroke: usize,
+    /// Should be even.
+    pub match_height: usize,
+    pub ins_stroke: usize,
+    pub min_mismatch_width: f64,
+    /// Draw individual bases if a base is wider than this.
+    pub min_base_width: f64,
+    pub del_opacity: f64,
+    pub clip_opacity: f64,
+    pub histogram_opacity: f64,
+    pub read_color: String,
+    pub deletion_color: String,
+    pub clip_color: String,
+    pub insertion_color: String,
+    pub depth_color: String,
+    /// Histogram of large deletions in the coverage track.
+    pub large_deletion_color: String,
+    /// Colours of A, C, G, T, and the other bases.
+    pub base_colors: [String; 5],
+    /// Colour of the matches when individual bases are drawn on them.
+    pub base_background_color: String,
+    /// Colour of the labels, the scale, and the matched bases.
+    pub text_color: String,
+    /// Colours of reads coloured by `--color-by`.
+    pub palette: Vec<String>,
+    pub group_font_size: usize,
+    pub feature_height: usize,
+    pub feature_font_size: usize,
+    pub arrow_head: f64,
+    pub link_stroke: usize,
+    pub link_height: f64,
+    /// Junctions of a jump forward, a jump backward, and a change of the strand.
+    pub link_deletion_color: String,
+    pub link_duplication_color: String,
+    pub link_inversion_color: String,
+    pub gene_color: String,
+    pub bed_color: String,
+    pub repeat_color: String,
+    pub scale_margin: usize,
+    pub top_margin: usize,
+    pub track_height: usize,
+    /// Between the bottom of the coverage track and the scale.
+    pub track_margin: usize,
+    pub side_margin: usize,
+    pub label_offset: usize,
+    pub tick_len: usize,
+    /// Interval of the ticks (bp). If not given, at most `max_ticks` ticks are drawn at a "nice" interval.
+    pub tick: Option<usize>,
+    pub max_ticks: usize,
+    pub tick_stroke: usize,
+    pub scale_stroke: usize,
+    pub scale_font_size: usize,
+    pub tick_font_size: usize,
+    /// Margin around the plot area of `dot_plot`, where the names of the contigs are written.
+    pub dot_margin: usize,
+    pub dot_font_size: usize,
+    pub dot_frame_stroke: usize,
+    /// Boundaries between contigs.
+    pub dot_separator_stroke: f64,
+    pub dot_separator_color: String,
+    pub dot_block_stroke: f64,
+    /// Alignments on the same strand and on the opposite strands.
+    pub dot_forward_color: String,
+    pub dot_reverse_color: String,
+    /// Opacity of the repeats, drawn in `repeat_color`.
+    pub dot_repeat_opacity: f64,
+}
+
+impl std::default::Default for Theme {
+    fn default() -> Self {
+        let to_strings =
+            |xs: &[&str]| -> Vec<String> { xs.iter().map(|x| x.to_string()).collect() };
+        let base_colors = ["green", "blue", "orange", "red", "gray"].map(|x| x.to_string());
+        let palette = [
+            "#1b9e77", "#d95f02", "#7570b3", "#e7298a", "#66a61e", "#e6ab02", "#a6761d", "#666666",
+        ];
+        Self {
+            width: 1500,
+            height_per_reads: 10,
+            del_stroke: 3,
+            match_height: 8,
+            ins_stroke: 1,
+            min_mismatch_width: 1f64,
+            min_base_width: 6f64,
+            del_opacity: 0.7,
+            clip_opacity: 0.3,
+            histogram_opacity: 0.7,
+            read_color: "black".to_string(),
+            deletion_color: "skyblue".to_string(),
+            clip_color: "red".to_string(),
+            insertion_color: "purple".to_string(),
+            depth_color: "gray".to_string(),
+            large_deletion_color: "blue".to_string(),
+            base_colors,
+            base_background_color: "lightgray".to_string(),
+            text_color: "black".to_string(),
+            palette: to_strings(&palette),
+            group_font_size: 12,
+            feature_height: 30,
+            feature_font_size: 12,
+            arrow_head: 6f64,
+            link_stroke: 1,
+            link_height: 8f64,
+            link_deletion_color: "blue".to_string(),
+            link_duplication_color: "green".to_string(),
+            link_inversion_color: "magenta".to_string(),
+            gene_color: "seagreen".to_string(),
+            bed_color: "steelblue".to_string(),
+            repeat_color: "orange".to_string(),
+            scale_margin: 20,
+            top_margin: 60,
+            track_height: 60,
+            track_margin: 30,
+            side_margin: 50,
+            label_offset: 75,
+            tick_len: 10,
+            tick: None,
+            max_ticks: 10,
+            tick_stroke: 2,
+            scale_stroke: 5,
+            scale_font_size: 25,
+            tick_font_size: 20,
+            dot_margin: 150,
+            dot_font_size: 14,
+            dot_frame_stroke: 2,
+            dot_separator_stroke: 0.5,
+            dot_separator_color: "gray".to_string(),
+            dot_block_stroke: 1.5,
+            dot_forward_color: "royalblue".to_string(),
+            dot_reverse_color: "crimson".to_string(),
+            dot_repeat_opacity: 0.2,
+        }
+    }
+}
+
+impl Theme {
+    /// Load the theme from a TOML file. Missing fields take the default values.
+    pub fn from_file(path: &Path) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| {
+            let msg = format!("{path:?}:{e}");
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, msg)
+        })
+    }
+    /// Height above the rows of reads, i.e., the coverage track and the scale.
+    pub fn pileup_margin(&self) -> usize {
+        self.scale_margin + self.track_margin + self.track_height + self.top_margin
+    }
+    pub fn base_color(&self, base: u8) -> &str {
+        let idx = match base {
+            b'A' => 0,
+            b'C' => 1,
+            b'G' => 2,
+            b'T' => 3,
+            _ => 4,
+        };
+        &self.base_colors[idx]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn partial_toml() {
+        let theme: Theme = toml::from_str("width = 800\ndot_forward_color = \"black\"").unwrap();
+        assert_eq!(theme.width, 800);
+        assert_eq!(theme.dot_forward_color, "black");
+        let default = Theme::default();
+        assert_eq!(theme.dot_margin, default.dot_margin);
+        assert_eq!(theme.palette, default.palette);
+    }
+}